@@ -1,15 +1,24 @@
 extern crate sdl2;
+extern crate sdl_window;
 
 use std::env;
-use std::path::Path;
+use std::fs;
 use std::time::{Duration, SystemTime};
 
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
 use sdl2::rect::Rect;
 use sdl2::render::TextureQuery;
+use sdl2::rwops::RWops;
 use sdl2::pixels::Color;
 
+use sdl_window::RenderConfig;
+
+// Built into the game so it runs from anywhere. A font file given on the command line is used
+// instead.
+static FONT: &[u8] = include_bytes!("../PatrickHand-Regular.ttf");
+
+// Everything is drawn to a virtual screen this size, then scaled to fit the window.
 static SCREEN_WIDTH : u32 = 160;
 static SCREEN_HEIGHT : u32 = 144;
 
@@ -20,7 +29,7 @@ macro_rules! rect(
     )
 );
 
-fn run(font_path: &Path) -> Result<(), String> {
+fn run(font_data: &[u8], config: RenderConfig) -> Result<(), String> {
 
     let color_dark = Color::RGBA(15, 56, 15, 255);
     let color_dim = Color::RGBA(48, 98, 48, 255);
@@ -31,22 +40,17 @@ fn run(font_path: &Path) -> Result<(), String> {
     let video_subsys = sdl_context.video()?;
     let ttf_context = sdl2::ttf::init().map_err(|e| e.to_string())?;
 
-    let window = video_subsys.window("SDL2_TTF Example", SCREEN_WIDTH, SCREEN_HEIGHT)
-        .position_centered()
-        .opengl()
-        .build()
-        .map_err(|e| e.to_string())?;
-
-    let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+    let (mut canvas, config) = sdl_window::open(&video_subsys, "SDL2_TTF Example", config)?;
+    sdl_window::fit_to_window(&mut canvas, SCREEN_WIDTH, SCREEN_HEIGHT)?;
     let texture_creator = canvas.texture_creator();
 
     canvas.set_draw_color(color_dark);
     canvas.clear();
 
     // Load a font
-    let font = ttf_context.load_font(font_path, 24)?;
-    let subtitle = ttf_context.load_font(font_path, 18)?;
-    let hint = ttf_context.load_font(font_path, 12)?;
+    let font = ttf_context.load_font_from_rwops(RWops::from_bytes(font_data)?, 24)?;
+    let subtitle = ttf_context.load_font_from_rwops(RWops::from_bytes(font_data)?, 18)?;
+    let hint = ttf_context.load_font_from_rwops(RWops::from_bytes(font_data)?, 12)?;
 
     //Title Text
     let titleSurface = font.render("Nightcrawlers!")
@@ -86,10 +90,16 @@ fn run(font_path: &Path) -> Result<(), String> {
     let two_sec = Duration::from_secs(2);
     let mut drawHint = false;
     'mainloop: loop {
+        // The window's contents are gone after a resize, so it is drawn again straight away.
+        let mut redraw = false;
         for event in sdl_context.event_pump()?.poll_iter() {
             match event {
                 Event::KeyDown {keycode: Some(Keycode::Escape), ..} |
                 Event::Quit {..} => break 'mainloop,
+                Event::KeyDown {keycode: Some(Keycode::F11), repeat: false, ..} => {
+                    sdl_window::toggle_fullscreen(&mut canvas)?;
+                }
+                Event::Window {win_event: WindowEvent::SizeChanged(..), ..} => redraw = true,
                 _ => {}
             }
         }
@@ -97,41 +107,57 @@ fn run(font_path: &Path) -> Result<(), String> {
         if (last_sec.elapsed().unwrap() > one_sec * 2 && drawHint) {
             last_sec = SystemTime::now();
             drawHint = false;
-            
-            println!("drawing!");
-            canvas.set_draw_color(color_dark);
-            canvas.clear();
-            canvas.copy(&titleTexture, None, Some(titleTarget))?;
-            canvas.copy(&subtitleTexture, None, Some(subtitleTarget))?;
-            canvas.present();
+            redraw = true;
         } else if (last_sec.elapsed().unwrap() > one_sec && !drawHint) {
             drawHint = true;
+            redraw = true;
+        }
 
-            println!("drawing!");
+        // With vsync every frame is shown, and waiting for the display paces the loop.
+        if redraw || config.vsync {
+            if redraw {
+                println!("drawing!");
+            }
             canvas.set_draw_color(color_dark);
             canvas.clear();
             canvas.copy(&titleTexture, None, Some(titleTarget))?;
             canvas.copy(&subtitleTexture, None, Some(subtitleTarget))?;
-            canvas.copy(&hintTexture, None, Some(hintTarget))?;
+            if drawHint {
+                canvas.copy(&hintTexture, None, Some(hintTarget))?;
+            }
             canvas.present();
-        }  
+        }
 
+        if !config.vsync && config.fps > 0 {
+            std::thread::sleep(Duration::new(0, 1_000_000_000u32 / config.fps));
+        }
     }
 
     Ok(())
 }
 
+// Takes the renderer options sdl_window knows, such as --renderer software, and a font file
+// to use instead of the built in one. Without vsync, --fps is how often input and the time
+// are checked.
 fn main() -> Result<(), String> {
-    let args: Vec<_> = env::args().collect();
+    let mut config = RenderConfig::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+    let mut font = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            _ if config.parse_arg(&arg, &mut args)? => {}
+            _ if arg.starts_with("--") => return Err(format!("unknown argument: {}", arg)),
+            _ => font = Some(arg),
+        }
+    }
 
     println!("linked sdl2_ttf: {}", sdl2::ttf::get_linked_version());
 
-    if args.len() < 2 {
-        println!("Usage: ./demo font.[ttf|ttc|fon]")
-    } else {
-        let path: &Path = Path::new(&args[1]);
-        run(path)?;
+    match font {
+        None => run(FONT, config),
+        Some(path) => {
+            let data = fs::read(&path).map_err(|e| format!("{}: {}", path, e))?;
+            run(&data, config)
+        }
     }
-
-    Ok(())
 }
\ No newline at end of file
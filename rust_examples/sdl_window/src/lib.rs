@@ -0,0 +1,169 @@
+// Opening the window a game draws in, shared by tiles, snaek and nightcrawlers: an accelerated
+// or software renderer picked at startup, a small virtual screen scaled up to fit, and
+// fullscreen on a key.
+
+use sdl2::render::Canvas;
+use sdl2::video::{FullscreenType, Window};
+use sdl2::VideoSubsystem;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    Software,
+    Accelerated,
+}
+
+pub fn parse_backend(name: &str) -> Result<Backend, String> {
+    match name {
+        "software" => Ok(Backend::Software),
+        "accelerated" => Ok(Backend::Accelerated),
+        _ => Err(format!(
+            "unknown renderer {}, expected software or accelerated",
+            name
+        )),
+    }
+}
+
+// How frames get drawn and shown.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderConfig {
+    pub backend: Backend,
+    // Wait for the display before showing each frame. Only accelerated renderers can.
+    pub vsync: bool,
+    // Frames shown a second when not waiting for the display, 0 for as many as possible.
+    pub fps: u32,
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: bool,
+}
+
+impl RenderConfig {
+    // Accelerated and vsynced, in a window width x height.
+    pub fn new(width: u32, height: u32) -> RenderConfig {
+        RenderConfig {
+            backend: Backend::Accelerated,
+            vsync: true,
+            fps: 60,
+            width,
+            height,
+            fullscreen: false,
+        }
+    }
+
+    // Takes one of --renderer software|accelerated, --vsync, --no-vsync, --fps <frames a
+    // second> or --fullscreen off the command line, reading any value from rest. Returns
+    // false for anything else, so the game can look at it.
+    pub fn parse_arg<I>(&mut self, arg: &str, rest: &mut I) -> Result<bool, String>
+    where
+        I: Iterator<Item = String>,
+    {
+        match arg {
+            "--renderer" => {
+                let name = rest
+                    .next()
+                    .ok_or("--renderer needs software or accelerated")?;
+                self.backend = parse_backend(&name)?;
+            }
+            "--vsync" => self.vsync = true,
+            "--no-vsync" => self.vsync = false,
+            "--fps" => {
+                let fps = rest.next().ok_or("--fps needs a number")?;
+                self.fps = fps.parse::<u32>().map_err(|e| e.to_string())?;
+            }
+            "--fullscreen" => self.fullscreen = true,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+fn window(video: &VideoSubsystem, title: &str, config: &RenderConfig) -> Result<Window, String> {
+    let mut builder = video.window(title, config.width, config.height);
+    builder.position_centered().resizable();
+    if config.fullscreen {
+        builder.fullscreen_desktop();
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+// Opens the window with the renderer asked for. When acceleration isn't available, such as
+// with the dummy video driver, it says so and falls back to software. Building a canvas uses
+// up its window, so the fallback gets a new one. Returns what was actually picked.
+pub fn open(
+    video: &VideoSubsystem,
+    title: &str,
+    config: RenderConfig,
+) -> Result<(Canvas<Window>, RenderConfig), String> {
+    if config.backend == Backend::Accelerated {
+        let mut builder = window(video, title, &config)?.into_canvas().accelerated();
+        if config.vsync {
+            builder = builder.present_vsync();
+        }
+        match builder.build() {
+            Ok(canvas) => return Ok((canvas, config)),
+            Err(e) => eprintln!("no accelerated renderer ({}), using software", e),
+        }
+    }
+
+    let canvas = window(video, title, &config)?
+        .into_canvas()
+        .software()
+        .build()
+        .map_err(|e| e.to_string())?;
+    Ok((
+        canvas,
+        RenderConfig {
+            backend: Backend::Software,
+            vsync: false,
+            ..config
+        },
+    ))
+}
+
+// Has SDL scale a w x h virtual screen up by a whole number to fit the window, with bars
+// around it, and map mouse positions back to virtual pixels. It keeps this up as the window
+// resizes.
+pub fn fit_to_window(canvas: &mut Canvas<Window>, w: u32, h: u32) -> Result<(), String> {
+    canvas.set_logical_size(w, h).map_err(|e| e.to_string())?;
+    // Not wrapped by the sdl2 crate.
+    let integer = unsafe {
+        sdl2::sys::SDL_RenderSetIntegerScale(canvas.raw(), sdl2::sys::SDL_bool::SDL_TRUE)
+    };
+    if integer != 0 {
+        return Err(sdl2::get_error());
+    }
+    Ok(())
+}
+
+pub fn toggle_fullscreen(canvas: &mut Canvas<Window>) -> Result<(), String> {
+    let window = canvas.window_mut();
+    let mode = match window.fullscreen_state() {
+        FullscreenType::Off => FullscreenType::Desktop,
+        _ => FullscreenType::Off,
+    };
+    window.set_fullscreen(mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_renderer_arguments_and_leaves_the_rest() {
+        let mut config = RenderConfig::new(320, 240);
+        let mut rest = vec!["software".to_string(), "30".to_string()].into_iter();
+        assert_eq!(config.parse_arg("--renderer", &mut rest), Ok(true));
+        assert_eq!(config.parse_arg("--no-vsync", &mut rest), Ok(true));
+        assert_eq!(config.parse_arg("--fps", &mut rest), Ok(true));
+        assert_eq!(config.parse_arg("font.ttf", &mut rest), Ok(false));
+        assert_eq!(
+            config,
+            RenderConfig {
+                backend: Backend::Software,
+                vsync: false,
+                fps: 30,
+                ..RenderConfig::new(320, 240)
+            }
+        );
+        assert!(config.parse_arg("--renderer", &mut rest).is_err());
+    }
+}
@@ -1,8 +1,10 @@
 extern crate rand;
 extern crate sdl2;
+extern crate sdl_window;
 
 use std::cmp;
-use std::time::Duration;
+use std::env;
+use std::time::{Duration, Instant};
 
 use rand::prelude::*;
 
@@ -12,6 +14,9 @@ use sdl2::pixels;
 
 use sdl2::gfx::primitives::DrawRenderer;
 
+use sdl_window::RenderConfig;
+
+// Everything is drawn to a virtual screen this size, then scaled to fit the window.
 const SCREEN_WIDTH: u32 = 320;
 const SCREEN_HEIGHT: u32 = 240;
 
@@ -60,17 +65,25 @@ fn dir_to_delta(dir: SnakeDir) -> (i32, i32) {
     };
 }
 
+// Takes the renderer options sdl_window knows, such as --renderer software or --fps 30. The
+// snake moves at its own pace whatever the frame rate.
+fn parse_args() -> Result<RenderConfig, String> {
+    let mut config = RenderConfig::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if !config.parse_arg(&arg, &mut args)? {
+            return Err(format!("unknown argument: {}", arg));
+        }
+    }
+    Ok(config)
+}
+
 fn main() -> Result<(), String> {
+    let config = parse_args()?;
     let sdl_context = sdl2::init()?;
     let video_subsys = sdl_context.video()?;
-    let window = video_subsys
-        .window("Snaek", SCREEN_WIDTH, SCREEN_HEIGHT)
-        .position_centered()
-        .opengl()
-        .build()
-        .map_err(|e| e.to_string())?;
-
-    let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+    let (mut canvas, config) = sdl_window::open(&video_subsys, "Snaek", config)?;
+    sdl_window::fit_to_window(&mut canvas, SCREEN_WIDTH, SCREEN_HEIGHT)?;
 
     let mut events = sdl_context.event_pump()?;
 
@@ -78,10 +91,12 @@ fn main() -> Result<(), String> {
     let mut dir = SnakeDir::Right;
     let mut block = random_block(&snake);
     let mut dead = false;
+    // Moves a second.
     let mut frames = 10;
+    let mut new_dir = dir;
+    let mut next_move = Instant::now();
 
     'main: loop {
-        let mut new_dir = dir;
         for event in events.poll_iter() {
             match event {
                 Event::Quit { .. } => break 'main,
@@ -91,6 +106,8 @@ fn main() -> Result<(), String> {
                 } => {
                     if keycode == Keycode::Escape {
                         break 'main;
+                    } else if keycode == Keycode::F11 {
+                        sdl_window::toggle_fullscreen(&mut canvas)?;
                     } else if keycode == Keycode::Left {
                         if SnakeDir::Left != opp_dir(dir) {
                             new_dir = SnakeDir::Left;
@@ -113,35 +130,39 @@ fn main() -> Result<(), String> {
             }
         }
 
-        let (dir_x, dir_y) = dir_to_delta(dir);
-        dir = new_dir;
+        // The snake moves at its own pace, however often the screen is drawn.
+        if Instant::now() >= next_move {
+            next_move = Instant::now() + Duration::new(0, 1_000_000_000u32 / frames);
+            let (dir_x, dir_y) = dir_to_delta(dir);
+            dir = new_dir;
+
+            // Do the logic.
+            if !dead {
+                let new_point = *snake.last().unwrap(); //[snake.len() - 1];
+                let nx = snake[0].x + dir_x;
+                let ny = snake[0].y + dir_y;
+
+                for i in (1..snake.len()).rev() {
+                    snake[i].x = snake[i - 1].x;
+                    snake[i].y = snake[i - 1].y;
+                    if nx == snake[i].x && ny == snake[i].y {
+                        dead = true;
+                    }
+                }
 
-        // Do the logic.
-        if !dead {
-            let new_point = *snake.last().unwrap(); //[snake.len() - 1];
-            let nx = snake[0].x + dir_x;
-            let ny = snake[0].y + dir_y;
+                snake[0].x = nx;
+                snake[0].y = ny;
 
-            for i in (1..snake.len()).rev() {
-                snake[i].x = snake[i - 1].x;
-                snake[i].y = snake[i - 1].y;
-                if nx == snake[i].x && ny == snake[i].y {
+                if snake[0].x < 0 || snake[0].x > 31 || snake[0].y < 0 || snake[0].y > 23 {
                     dead = true;
                 }
-            }
 
-            snake[0].x = nx;
-            snake[0].y = ny;
-
-            if snake[0].x < 0 || snake[0].x > 31 || snake[0].y < 0 || snake[0].y > 23 {
-                dead = true;
-            }
-
-            if snake[0] == block {
-                snake.push(new_point);
-                block = random_block(&snake);
-                frames += 2;
-                frames = cmp::min(60, frames);
+                if snake[0] == block {
+                    snake.push(new_point);
+                    block = random_block(&snake);
+                    frames += 2;
+                    frames = cmp::min(60, frames);
+                }
             }
         }
 
@@ -166,7 +187,10 @@ fn main() -> Result<(), String> {
 
         canvas.present();
 
-        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / frames));
+        // A vsynced present already waits for the display.
+        if !config.vsync && config.fps > 0 {
+            ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / config.fps));
+        }
     }
 
     Ok(())
@@ -0,0 +1,61 @@
+use std::collections::BTreeSet;
+
+use tiles::event::Subscriber;
+
+use crate::GameEvent;
+
+// Items to pick up for the collector achievement, and monsters to kill for the slayer one.
+const COLLECTOR_ITEMS: u32 = 5;
+const SLAYER_KILLS: u32 = 10;
+
+// Achievements worked out from gameplay events. They are only kept for the session.
+pub struct Achievements {
+    unlocked: BTreeSet<&'static str>,
+    // Unlocked since the last call to take_new, for the HUD to announce.
+    new: Vec<&'static str>,
+    kills: u32,
+    items: u32,
+}
+
+impl Achievements {
+    pub fn new() -> Achievements {
+        Achievements {
+            unlocked: BTreeSet::new(),
+            new: Vec::new(),
+            kills: 0,
+            items: 0,
+        }
+    }
+
+    fn unlock(&mut self, name: &'static str) {
+        if self.unlocked.insert(name) {
+            self.new.push(name);
+        }
+    }
+
+    pub fn take_new(&mut self) -> Vec<&'static str> {
+        self.new.drain(..).collect()
+    }
+}
+
+impl Subscriber<GameEvent> for Achievements {
+    fn notify(&mut self, event: &GameEvent) -> Result<(), String> {
+        match event {
+            GameEvent::CharacterDied { .. } => {
+                self.kills += 1;
+                self.unlock("first blood");
+                if self.kills >= SLAYER_KILLS {
+                    self.unlock("slayer");
+                }
+            }
+            GameEvent::ItemPicked { .. } => {
+                self.items += 1;
+                if self.items >= COLLECTOR_ITEMS {
+                    self.unlock("collector");
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+// A pack is every asset in one file, so the game can be moved or embedded whole:
+//
+//   "TPAK", then the number of files
+//   for each file: the length of its name, its name, and where its bytes start and how many
+//   there are, counted from the start of the pack
+//   the files' bytes
+//
+// Every number is a little-endian u32. Names use / whatever the platform, such as
+// "dialogue/old_man.json".
+const MAGIC: &[u8; 4] = b"TPAK";
+
+pub struct Pack {
+    data: Cow<'static, [u8]>,
+    index: HashMap<String, Range<usize>>,
+}
+
+// Reads the u32 at *at and moves past it.
+fn read_u32(data: &[u8], at: &mut usize) -> Result<usize, String> {
+    let bytes = data
+        .get(*at..*at + 4)
+        .ok_or("pack is cut short in its index")?;
+    *at += 4;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+}
+
+impl Pack {
+    // Takes bytes from include_bytes! as they are, or a file read into memory.
+    pub fn parse(data: Cow<'static, [u8]>) -> Result<Pack, String> {
+        if !data.starts_with(MAGIC) {
+            return Err("not an asset pack".to_string());
+        }
+        let mut at = MAGIC.len();
+        let count = read_u32(&data, &mut at)?;
+        let mut index = HashMap::new();
+        for _ in 0..count {
+            let len = read_u32(&data, &mut at)?;
+            let name = data
+                .get(at..at + len)
+                .ok_or("pack is cut short in its index")?;
+            let name = String::from_utf8(name.to_vec()).map_err(|e| e.to_string())?;
+            at += len;
+            let start = read_u32(&data, &mut at)?;
+            let end = start + read_u32(&data, &mut at)?;
+            if end > data.len() {
+                return Err(format!("{} runs past the end of the pack", name));
+            }
+            if index.insert(name.clone(), start..end).is_some() {
+                return Err(format!("{} is in the pack twice", name));
+            }
+        }
+        Ok(Pack { data, index })
+    }
+
+    pub fn load(path: &str) -> Result<Pack, String> {
+        let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        Pack::parse(Cow::Owned(data)).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.index.get(name).map(|range| &self.data[range.clone()])
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.index.keys().map(String::as_str)
+    }
+}
+
+// Builds a pack from (name, bytes) pairs, in the order given.
+pub fn build(files: &[(String, Vec<u8>)]) -> Result<Vec<u8>, String> {
+    let index_len: usize = files.iter().map(|(name, _)| 12 + name.len()).sum();
+    let mut start = MAGIC.len() + 4 + index_len;
+    let mut pack = MAGIC.to_vec();
+    let put = |pack: &mut Vec<u8>, n: usize| -> Result<(), String> {
+        let n = u32::try_from(n).map_err(|_| "pack is over 4GB".to_string())?;
+        pack.extend_from_slice(&n.to_le_bytes());
+        Ok(())
+    };
+
+    put(&mut pack, files.len())?;
+    for (name, bytes) in files {
+        put(&mut pack, name.len())?;
+        pack.extend_from_slice(name.as_bytes());
+        put(&mut pack, start)?;
+        put(&mut pack, bytes.len())?;
+        start += bytes.len();
+    }
+    for (_, bytes) in files {
+        pack.extend_from_slice(bytes);
+    }
+    Ok(pack)
+}
+
+// The pack the game was started with, if any. Assets are read all over the place, from level
+// loading to scripts, and used to be found through the current directory, which is just as
+// global. It can only be set once, at startup.
+static MOUNTED: OnceLock<Pack> = OnceLock::new();
+
+pub fn mount(pack: Pack) -> Result<(), String> {
+    MOUNTED
+        .set(pack)
+        .map_err(|_| "an asset pack is already mounted".to_string())
+}
+
+// Where loose files are read from, assets.dir in the config. Also set once, at startup, so
+// paths given on the command line are still relative to the directory the game was run from.
+// The current directory until it is set.
+static DIR: OnceLock<PathBuf> = OnceLock::new();
+
+pub fn set_dir(dir: &str) -> Result<(), String> {
+    if !Path::new(dir).is_dir() {
+        return Err(format!("{}: no such directory", dir));
+    }
+    DIR.set(PathBuf::from(dir))
+        .map_err(|_| "the asset directory is already set".to_string())
+}
+
+fn path(name: &str) -> PathBuf {
+    match DIR.get() {
+        Some(dir) => dir.join(name),
+        None => PathBuf::from(name),
+    }
+}
+
+// An asset's bytes from the mounted pack, which stay around for as long as the game runs.
+pub fn packed(name: &str) -> Option<&'static [u8]> {
+    MOUNTED.get().and_then(|pack| pack.get(name))
+}
+
+// An asset from the mounted pack, or from a file in the asset directory when it isn't
+// packed, so loose files still work while making the game.
+pub fn read(name: &str) -> Result<Cow<'static, [u8]>, String> {
+    match packed(name) {
+        Some(bytes) => Ok(Cow::Borrowed(bytes)),
+        None => fs::read(path(name))
+            .map(Cow::Owned)
+            .map_err(|e| format!("{}: {}", name, e)),
+    }
+}
+
+pub fn read_to_string(name: &str) -> Result<String, String> {
+    let bytes = read(name)?;
+    String::from_utf8(bytes.into_owned()).map_err(|e| format!("{}: {}", name, e))
+}
+
+pub fn exists(name: &str) -> bool {
+    packed(name).is_some() || path(name).exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_each_file_by_name() {
+        let files = vec![
+            ("map.json".to_string(), b"{}".to_vec()),
+            ("dialogue/old_man.json".to_string(), b"hello".to_vec()),
+            ("empty".to_string(), Vec::new()),
+        ];
+        let pack = Pack::parse(Cow::Owned(build(&files).unwrap())).unwrap();
+        assert_eq!(pack.get("map.json"), Some(&b"{}"[..]));
+        assert_eq!(pack.get("dialogue/old_man.json"), Some(&b"hello"[..]));
+        assert_eq!(pack.get("empty"), Some(&b""[..]));
+        assert_eq!(pack.get("music.mp3"), None);
+        assert_eq!(pack.names().count(), 3);
+    }
+
+    #[test]
+    fn rejects_broken_packs() {
+        let files = vec![("a".to_string(), b"abc".to_vec())];
+        let data = build(&files).unwrap();
+        assert!(Pack::parse(Cow::Borrowed(&b"PK\x03\x04"[..])).is_err());
+        let err = Pack::parse(Cow::Owned(data[..data.len() - 1].to_vec()))
+            .err()
+            .unwrap();
+        assert_eq!(err, "a runs past the end of the pack");
+        assert!(Pack::parse(Cow::Owned(data[..10].to_vec())).is_err());
+    }
+}
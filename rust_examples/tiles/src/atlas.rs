@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::ops::Index;
+
+use crate::assets;
+
+// What a tile does to whoever stands on it, and whether it lights up the dark. Set per tile
+// type in the atlas after the position and size, e.g. "hole 96 144 16 16 damage=25" or
+// "wall_mid 32 16 16 16 solid".
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileProps {
+    pub solid: bool,
+    // Health lost per second.
+    pub damage: f32,
+    // Multiplies the walking speed.
+    pub speed: f32,
+    pub footstep: Option<String>,
+    // Radius of the light it gives off in map pixels, 0 for none.
+    pub light: u32,
+    pub light_colour: [u8; 3],
+}
+
+// The props of a tile that doesn't say anything.
+pub const PLAIN: TileProps = TileProps {
+    solid: false,
+    damage: 0.0,
+    speed: 1.0,
+    footstep: None,
+    light: 0,
+    light_colour: [255, 255, 255],
+};
+
+impl Default for TileProps {
+    fn default() -> TileProps {
+        PLAIN
+    }
+}
+
+#[derive(Debug)]
+pub struct Tile {
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+    pub texture: String,
+    pub props: TileProps,
+}
+
+#[derive(Debug)]
+pub struct Animation {
+    pub w: u32,
+    pub h: u32,
+    pub tiles: Vec<Tile>,
+}
+
+// A handle to one of the Animations, so whatever is playing it doesn't have to borrow it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AnimId(usize);
+
+// Every animation from the atlases, looked up by name once and by handle after that.
+pub struct Animations {
+    anims: Vec<Animation>,
+    names: Vec<String>,
+    ids: HashMap<String, AnimId>,
+}
+
+impl Animations {
+    // Handles are given out in name order, so they are the same every run.
+    pub fn new(anims: HashMap<String, Animation>) -> Animations {
+        let mut sorted: Vec<(String, Animation)> = anims.into_iter().collect();
+        sorted.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut animations = Animations {
+            anims: Vec::new(),
+            names: Vec::new(),
+            ids: HashMap::new(),
+        };
+        for (name, anim) in sorted {
+            animations
+                .ids
+                .insert(name.clone(), AnimId(animations.anims.len()));
+            animations.names.push(name);
+            animations.anims.push(anim);
+        }
+        animations
+    }
+
+    pub fn id(&self, name: &str) -> Result<AnimId, String> {
+        match self.ids.get(name) {
+            Some(id) => Ok(*id),
+            None => Err(format!("no animation named {}", name)),
+        }
+    }
+
+    pub fn name(&self, id: AnimId) -> &str {
+        &self.names[id.0]
+    }
+}
+
+impl Index<AnimId> for Animations {
+    type Output = Animation;
+
+    fn index(&self, id: AnimId) -> &Animation {
+        &self.anims[id.0]
+    }
+}
+
+fn parse_props(words: &[&str]) -> Result<TileProps, String> {
+    let mut props = TileProps::default();
+    for word in words {
+        let mut kv = word.splitn(2, '=');
+        let bad = |e: &dyn ToString| format!("{}: {}", word, e.to_string());
+        match (kv.next().unwrap(), kv.next()) {
+            ("solid", None) => props.solid = true,
+            ("damage", Some(v)) => props.damage = v.parse::<f32>().map_err(|e| bad(&e))?,
+            ("speed", Some(v)) => props.speed = v.parse::<f32>().map_err(|e| bad(&e))?,
+            ("footstep", Some(v)) => props.footstep = Some(v.to_string()),
+            ("light", Some(v)) => props.light = v.parse::<u32>().map_err(|e| bad(&e))?,
+            // As rrggbb hex.
+            ("light_colour", Some(v)) => {
+                let rgb = u32::from_str_radix(v, 16).map_err(|e| bad(&e))?;
+                props.light_colour = [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8];
+            }
+            _ => return Err(format!("unknown tile property {}", word)),
+        }
+    }
+    Ok(props)
+}
+
+fn parse_line(
+    words: &[&str],
+    texture: &str,
+    tiles: &mut HashMap<String, Tile>,
+    anims: &mut HashMap<String, Animation>,
+) -> Result<(), String> {
+    if words.is_empty() {
+        return Ok(());
+    }
+    // The name and the numbers come first, anything after them is a property.
+    let numbers = words
+        .iter()
+        .skip(1)
+        .take_while(|w| w.parse::<i32>().is_ok())
+        .count();
+    let (s, props) = words.split_at((numbers + 1).min(words.len()));
+    let props = parse_props(props)?;
+    let size = |word: &str| {
+        word.parse::<u32>()
+            .map_err(|_| format!("{} isn't a size", word))
+    };
+
+    if s.len() == 5 {
+        tiles.insert(
+            String::from(s[0]),
+            Tile {
+                x: s[1].parse::<i32>().unwrap(),
+                y: s[2].parse::<i32>().unwrap(),
+                w: size(s[3])?,
+                h: size(s[4])?,
+                texture: texture.to_string(),
+                props,
+            },
+        );
+    } else if s.len() == 6 {
+        let count = s[5].parse::<i32>().unwrap();
+        if count <= 0 {
+            return Err(format!("{} has {} frames", s[0], count));
+        }
+        let mut anim = Animation {
+            w: size(s[3])?,
+            h: size(s[4])?,
+            tiles: Vec::new(),
+        };
+        let x = s[1].parse::<i32>().unwrap();
+        let y = s[2].parse::<i32>().unwrap();
+
+        for i in 0..count {
+            anim.tiles.push(Tile {
+                x: x + (anim.w as i32) * i,
+                y,
+                w: anim.w,
+                h: anim.h,
+                texture: texture.to_string(),
+                props: props.clone(),
+            });
+        }
+        anims.insert(String::from(s[0]), anim);
+    } else {
+        return Err(format!(
+            "expected x y w h and maybe a frame count after {}, found {} numbers",
+            s[0],
+            s.len() - 1
+        ));
+    }
+    Ok(())
+}
+
+// The tiles and animations read from an atlas, by name.
+pub type Parsed = (HashMap<String, Tile>, HashMap<String, Animation>);
+
+// Reads an atlas, one tile or animation per line:
+//   name x y w h [props] for a tile
+//   name x y w h count [props] for an animation, with w and h the size of a frame
+// Problems are reported as "path:line: message", since the atlases are edited by hand.
+pub fn parse_atlas(path: &str, text: &str, texture: String) -> Result<Parsed, String> {
+    let mut tiles: HashMap<String, Tile> = HashMap::new();
+    let mut anims: HashMap<String, Animation> = HashMap::new();
+
+    for (n, line) in text.split("\n").enumerate() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        parse_line(&words, &texture, &mut tiles, &mut anims)
+            .map_err(|e| format!("{}:{}: {}", path, n + 1, e))?;
+    }
+
+    Ok((tiles, anims))
+}
+
+pub fn parse_tiles(path: &str, texture: String) -> Result<Parsed, String> {
+    parse_atlas(path, &assets::read_to_string(path)?, texture)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_tiles_animations_and_props() {
+        let text = "wall_mid 32 16 16 16 solid\nimp_anim 0 0 16 16 2 light=40\n";
+        let (tiles, anims) = parse_atlas("a.txt", text, "generic".to_string()).unwrap();
+        assert!(tiles["wall_mid"].props.solid);
+        let imp = &anims["imp_anim"];
+        assert_eq!(imp.tiles.len(), 2);
+        assert_eq!(imp.tiles[1].x, 16);
+        assert_eq!(imp.tiles[1].props.light, 40);
+    }
+
+    #[test]
+    fn reports_the_line_of_a_bad_property() {
+        let text = "wall_mid 32 16 16 16 solid\nhole 96 144 16 16 damag=25\n";
+        let err = parse_atlas("a.txt", text, "generic".to_string());
+        assert_eq!(
+            err.err().unwrap(),
+            "a.txt:2: unknown tile property damag=25"
+        );
+        let err = parse_atlas("a.txt", "hole 0 0 16 16 light=lots", "generic".to_string());
+        assert_eq!(
+            err.err().unwrap(),
+            "a.txt:1: light=lots: invalid digit found in string"
+        );
+    }
+
+    #[test]
+    fn reports_missing_numbers_and_empty_animations() {
+        let text = "wall_mid 32 16 16 16\n\nhole 96 144 16 solid\n";
+        let err = parse_atlas("a.txt", text, "generic".to_string());
+        assert_eq!(
+            err.err().unwrap(),
+            "a.txt:3: expected x y w h and maybe a frame count after hole, found 3 numbers"
+        );
+        let err = parse_atlas("a.txt", "imp_anim 0 0 16 16 0", "generic".to_string());
+        assert_eq!(err.err().unwrap(), "a.txt:1: imp_anim has 0 frames");
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+
+use sdl2::mixer::{Channel, Chunk, LoaderRWops};
+use sdl2::rwops::RWops;
+
+use tiles::assets;
+use tiles::config;
+use tiles::event::Subscriber;
+
+use crate::GameEvent;
+
+// Plays the sounds for gameplay events. Sounds are loaded the first time they are needed.
+pub struct Audio {
+    chunks: HashMap<String, Chunk>,
+    pickup_sound: String,
+    caught_sound: String,
+}
+
+impl Audio {
+    pub fn new(config: &config::Audio) -> Audio {
+        Audio {
+            chunks: HashMap::new(),
+            pickup_sound: config.pickup_sound.clone(),
+            caught_sound: config.caught_sound.clone(),
+        }
+    }
+
+    // Plays a sound once more than loops, on any free channel. Running out of channels just
+    // means it isn't heard.
+    fn play(&mut self, name: &str, loops: i32) -> Result<(), String> {
+        if !self.chunks.contains_key(name) {
+            let bytes = assets::read(name)?;
+            let chunk = RWops::from_bytes(&bytes)?.load_wav()?;
+            self.chunks.insert(name.to_string(), chunk);
+        }
+        let _ = Channel::all().play(&self.chunks[name], loops);
+        Ok(())
+    }
+}
+
+impl Subscriber<GameEvent> for Audio {
+    fn notify(&mut self, event: &GameEvent) -> Result<(), String> {
+        let (name, loops) = match event {
+            GameEvent::EnemyReachedPlayer(_) => (self.caught_sound.clone(), 1),
+            GameEvent::ItemPicked { .. } => (self.pickup_sound.clone(), 0),
+            GameEvent::Sound(name) => (name.clone(), 0),
+            _ => return Ok(()),
+        };
+        self.play(&name, loops)
+    }
+}
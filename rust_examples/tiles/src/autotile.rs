@@ -0,0 +1,168 @@
+use crate::grid::{Grid, CELL};
+use crate::map::{MapForbidden, MapTile};
+
+// Neighbour bits, clockwise from north.
+const N: u8 = 1;
+const NE: u8 = 2;
+const E: u8 = 4;
+const SE: u8 = 8;
+const S: u8 = 16;
+const SW: u8 = 32;
+const W: u8 = 64;
+const NW: u8 = 128;
+
+// Checked in order, the first rule whose floor and solid neighbours are all present picks the
+// tile. A wall cell that matches nothing has no floor next to it and is left empty.
+const RULES: &[(u8, u8, &str)] = &[
+    // Floor below: the front face of the wall.
+    (S, SW | SE, "wall_mid"),
+    (S, SW, "wall_left"),
+    (S, SE, "wall_right"),
+    (S, 0, "wall_mid"),
+    // Floor to one side: the side walls of a room.
+    (E, 0, "wall_side_mid_left"),
+    (W, 0, "wall_side_mid_right"),
+    // Floor above: the top edge of the wall at the bottom of a room.
+    (N, 0, "wall_top_mid"),
+    // Only diagonal floor: the corners.
+    (SE, 0, "wall_side_top_left"),
+    (SW, 0, "wall_side_top_right"),
+    (NE, 0, "wall_side_front_left"),
+    (NW, 0, "wall_side_front_right"),
+];
+
+const FLOORS: &[&str] = &[
+    "floor_2", "floor_3", "floor_4", "floor_5", "floor_6", "floor_7", "floor_8",
+];
+
+fn floor_mask(grid: &Grid, x: i32, y: i32) -> u8 {
+    let around = [
+        (0, -1, N),
+        (1, -1, NE),
+        (1, 0, E),
+        (1, 1, SE),
+        (0, 1, S),
+        (-1, 1, SW),
+        (-1, 0, W),
+        (-1, -1, NW),
+    ];
+    let mut mask = 0;
+    for (dx, dy, bit) in &around {
+        if !grid.solid(x + dx, y + dy) {
+            mask |= bit;
+        }
+    }
+    mask
+}
+
+// The tile for a wall cell, or None if it can't be seen from any floor.
+pub fn wall_tile(grid: &Grid, x: i32, y: i32) -> Option<&'static str> {
+    let floor = floor_mask(grid, x, y);
+    let solid = !floor;
+    RULES
+        .iter()
+        .find(|(f, s, _)| floor & f == *f && solid & s == *s)
+        .map(|(_, _, tile)| *tile)
+}
+
+// Mostly plain floor with the odd cracked or mossy one. Picked from the position rather than an
+// rng so the same grid always gets the same floor.
+pub fn floor_tile(x: i32, y: i32) -> &'static str {
+    let h = (x as u32)
+        .wrapping_mul(73_856_093)
+        .wrapping_add((y as u32).wrapping_mul(19_349_663));
+    let h = (h ^ (h >> 13)).wrapping_mul(0x5bd1_e995);
+    let h = h ^ (h >> 15);
+    if h % 10 < 7 {
+        "floor_1"
+    } else {
+        FLOORS[(h / 10) as usize % FLOORS.len()]
+    }
+}
+
+pub fn tiles(grid: &Grid) -> Vec<MapTile> {
+    let mut tiles = Vec::new();
+    for y in 0..grid.h {
+        for x in 0..grid.w {
+            let tile = if grid.solid(x, y) {
+                match wall_tile(grid, x, y) {
+                    Some(tile) => tile,
+                    None => continue,
+                }
+            } else {
+                floor_tile(x, y)
+            };
+            tiles.push(MapTile {
+                x: x * CELL,
+                y: y * CELL,
+                tile: tile.to_string(),
+            });
+        }
+    }
+    tiles
+}
+
+// Collision rectangles covering exactly the solid cells.
+pub fn forbidden(grid: &Grid) -> Vec<MapForbidden> {
+    grid.solid_rects()
+        .into_iter()
+        .map(|(x, y, w, h)| MapForbidden {
+            x: x * CELL,
+            y: y * CELL,
+            w: (w * CELL) as u32,
+            h: (h * CELL) as u32,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::atlas::parse_tiles;
+
+    fn grid(rows: &[&str]) -> Grid {
+        Grid::from_layout(&rows.iter().map(|r| r.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn room_walls() {
+        let g = grid(&["#####", "#...#", "#...#", "#####"]);
+        assert_eq!(wall_tile(&g, 2, 0), Some("wall_mid"));
+        assert_eq!(wall_tile(&g, 1, 0), Some("wall_left"));
+        assert_eq!(wall_tile(&g, 3, 0), Some("wall_right"));
+        assert_eq!(wall_tile(&g, 0, 1), Some("wall_side_mid_left"));
+        assert_eq!(wall_tile(&g, 4, 2), Some("wall_side_mid_right"));
+        assert_eq!(wall_tile(&g, 2, 3), Some("wall_top_mid"));
+        assert_eq!(wall_tile(&g, 0, 0), Some("wall_side_top_left"));
+        assert_eq!(wall_tile(&g, 4, 3), Some("wall_side_front_right"));
+    }
+
+    #[test]
+    fn every_rule_names_an_atlas_tile() {
+        let (atlas, _) = parse_tiles("tiles_list.txt", "generic".to_string()).unwrap();
+        for (_, _, tile) in RULES {
+            assert!(atlas.contains_key(*tile), "{} is not in the atlas", tile);
+        }
+        for tile in FLOORS.iter().chain(&["floor_1"]) {
+            assert!(atlas.contains_key(*tile), "{} is not in the atlas", tile);
+        }
+    }
+
+    #[test]
+    fn forbidden_covers_the_walls() {
+        let g = grid(&["######", "#..#.#", "#....#", "######"]);
+        let rects = forbidden(&g);
+        for y in 0..g.h {
+            for x in 0..g.w {
+                let covered = rects.iter().any(|r| {
+                    x * CELL >= r.x
+                        && x * CELL < r.x + r.w as i32
+                        && y * CELL >= r.y
+                        && y * CELL < r.y + r.h as i32
+                });
+                assert_eq!(covered, g.solid(x, y), "cell {}, {}", x, y);
+            }
+        }
+    }
+}
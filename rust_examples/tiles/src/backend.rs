@@ -0,0 +1,16 @@
+use sdl_window::{parse_backend, RenderConfig};
+
+use tiles::config;
+
+// How the config asks for frames to be drawn and shown. The world steps FPS times a second
+// whatever fps is.
+pub fn render_config(video: &config::Video) -> Result<RenderConfig, String> {
+    Ok(RenderConfig {
+        backend: parse_backend(&video.renderer)?,
+        vsync: video.vsync,
+        fps: video.fps,
+        width: video.width,
+        height: video.height,
+        fullscreen: video.fullscreen,
+    })
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use cgmath::Vector2;
+
+use tiles::assets;
+use tiles::ecs::Entity;
+use tiles::script::{Body, Command, ScriptInput, TriggerView};
+
+use crate::components::{Animator, Brain, Characters, Sprite};
+use crate::{normalize, GameEvent, World};
+
+// What its script wants a character to do this frame, used by World::step in place of chasing
+// the player.
+pub struct Orders {
+    pub dir: Vector2<f32>,
+    pub aim: Option<Vector2<f32>>,
+}
+
+fn body(characters: &Characters, e: Entity) -> Body {
+    let pos = characters.pos(e);
+    Body {
+        name: match characters.names.get(e) {
+            Some(name) => name.clone(),
+            None => e.to_string(),
+        },
+        x: pos.x,
+        y: pos.y,
+        health: characters.healths.get(e).map_or(0.0, |h| h.0),
+    }
+}
+
+impl<'a> World<'a> {
+    // Runs the script of every scripted character, in id order so replays come out the same.
+    // Script state isn't saved, so scripts start over after loading.
+    pub fn run_scripts(&mut self) -> HashMap<Entity, Orders> {
+        let scripted: Vec<(Entity, String)> = self
+            .characters
+            .ais
+            .iter()
+            .filter_map(|(e, ai)| match &ai.brain {
+                Brain::Script { script, .. } => Some((e, script.clone())),
+                _ => None,
+            })
+            .collect();
+
+        let mut orders = HashMap::new();
+        for (e, script) in scripted {
+            let characters = &self.characters;
+            let input = ScriptInput {
+                me: body(characters, e),
+                player: body(characters, self.player),
+                others: characters
+                    .ais
+                    .iter()
+                    .filter(|(other, _)| *other != e && *other != self.player)
+                    .map(|(other, _)| body(characters, other))
+                    .collect(),
+                triggers: self
+                    .map
+                    .triggers
+                    .iter()
+                    .zip(&self.inside_triggers)
+                    .map(|(t, inside)| TriggerView {
+                        x: t.x,
+                        y: t.y,
+                        w: t.w,
+                        h: t.h,
+                        player_inside: *inside,
+                    })
+                    .collect(),
+            };
+
+            let commands = match &mut self.characters.ais.get_mut(e).unwrap().brain {
+                Brain::Script { state, .. } => self.scripts.run(&script, &input, state),
+                _ => Vec::new(),
+            };
+
+            let pos = self.characters.pos(e);
+            let mut order = Orders {
+                dir: Vector2::new(0.0, 0.0),
+                aim: None,
+            };
+            for command in commands {
+                match command {
+                    Command::MoveTowards(x, y) => order.dir = normalize(Vector2::new(x, y) - pos),
+                    Command::MoveDir(x, y) => order.dir = normalize(Vector2::new(x, y)),
+                    Command::Stop => order.dir = Vector2::new(0.0, 0.0),
+                    Command::PlayAnim(anim) => match self.anims.id(&anim) {
+                        // Asking for the animation it already has every frame mustn't keep
+                        // restarting it.
+                        Ok(anim) if self.characters.sprites.get(e).unwrap().idle != anim => {
+                            self.characters.sprites.insert(
+                                e,
+                                Sprite {
+                                    idle: anim,
+                                    walk: anim,
+                                },
+                            );
+                            let mut animator = Animator::new();
+                            animator.frame_rate = self.tuning.animation_frames;
+                            self.characters.animators.insert(e, animator);
+                        }
+                        Ok(_) => {}
+                        Err(err) => self.scripts.log(format!("{}: {}", input.me.name, err)),
+                    },
+                    Command::PlaySound(sound) => {
+                        if assets::exists(&sound) {
+                            self.events.publish(GameEvent::Sound(sound));
+                        } else {
+                            self.scripts
+                                .log(format!("{}: no sound named {}", input.me.name, sound));
+                        }
+                    }
+                    Command::FireAt(x, y) => order.aim = Some(Vector2::new(x, y) - pos),
+                    Command::Say(text) => self.events.publish(GameEvent::Message(text)),
+                }
+            }
+            orders.insert(e, order);
+        }
+        orders
+    }
+}
@@ -0,0 +1,26 @@
+use std::env;
+use std::fs;
+
+use tiles::map::Map;
+
+// Bakes a map's layout into tiles and forbidden rectangles, for touching up by hand after.
+fn run(args: &[String]) -> Result<(), String> {
+    if args.len() != 2 {
+        return Err("usage: autotile <in.json> <out.json>".to_string());
+    }
+
+    let data = fs::read_to_string(&args[0]).map_err(|e| format!("{}: {}", args[0], e))?;
+    let mut map: Map = serde_json::from_str(&data).map_err(|e| format!("{}: {}", args[0], e))?;
+    if map.layout.is_empty() {
+        return Err(format!("{}: map has no layout", args[0]));
+    }
+    map.apply_layout();
+
+    let data = serde_json::to_string_pretty(&map).map_err(|e| e.to_string())?;
+    fs::write(&args[1], data).map_err(|e| format!("{}: {}", args[1], e))
+}
+
+fn main() -> Result<(), String> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    run(&args)
+}
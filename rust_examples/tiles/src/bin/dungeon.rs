@@ -0,0 +1,41 @@
+use std::env;
+use std::fs;
+
+use tiles::dungeon::{self, Style};
+
+fn run(args: &[String]) -> Result<(), String> {
+    if args.len() < 2 {
+        return Err("usage: dungeon <seed> <out.json> [--caves] [--size WxH]".to_string());
+    }
+
+    let seed = args[0].parse::<u64>().map_err(|e| e.to_string())?;
+    let out = &args[1];
+    let mut style = Style::Rooms;
+    let (mut w, mut h) = (40, 30);
+
+    let mut rest = args[2..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--caves" => style = Style::Caves,
+            "--size" => {
+                let size = rest.next().ok_or("--size needs WxH")?;
+                let wh: Vec<&str> = size.split('x').collect();
+                if wh.len() != 2 {
+                    return Err(format!("--size expects WxH, got {}", size));
+                }
+                w = wh[0].parse::<i32>().map_err(|e| e.to_string())?;
+                h = wh[1].parse::<i32>().map_err(|e| e.to_string())?;
+            }
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+
+    let map = dungeon::generate(seed, w, h, style)?;
+    let data = serde_json::to_string_pretty(&map).map_err(|e| e.to_string())?;
+    fs::write(out, data).map_err(|e| format!("{}: {}", out, e))
+}
+
+fn main() -> Result<(), String> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    run(&args)
+}
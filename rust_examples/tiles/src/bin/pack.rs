@@ -0,0 +1,57 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use tiles::assets;
+
+// Adds a file, or everything under a directory in name order, named by its path with / between
+// the parts so the pack is the same on every platform.
+fn add(path: &Path, files: &mut Vec<(String, Vec<u8>)>) -> Result<(), String> {
+    let shown = path.display();
+    if path.is_dir() {
+        let mut entries: Vec<_> = fs::read_dir(path)
+            .map_err(|e| format!("{}: {}", shown, e))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("{}: {}", shown, e))?;
+        entries.sort();
+        for entry in entries {
+            add(&entry, files)?;
+        }
+        return Ok(());
+    }
+
+    let name = path
+        .components()
+        .filter_map(|part| part.as_os_str().to_str())
+        .filter(|part| *part != ".")
+        .collect::<Vec<_>>()
+        .join("/");
+    if files.iter().any(|(n, _)| *n == name) {
+        return Err(format!("{} is given twice", name));
+    }
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", shown, e))?;
+    files.push((name, bytes));
+    Ok(())
+}
+
+// Packs assets into one file for the game to load from next to its executable, or to embed.
+// Run it from the asset directory so the names match what the game asks for, e.g.
+//   pack assets.pack tiles.png tiles_list.txt map.json dialogue scripts
+fn run(args: &[String]) -> Result<(), String> {
+    if args.len() < 2 {
+        return Err("usage: pack <out.pack> <file or directory>...".to_string());
+    }
+
+    let mut files = Vec::new();
+    for arg in &args[1..] {
+        add(Path::new(arg), &mut files)?;
+    }
+    let pack = assets::build(&files)?;
+    fs::write(&args[0], pack).map_err(|e| format!("{}: {}", args[0], e))
+}
+
+fn main() -> Result<(), String> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    run(&args)
+}
@@ -0,0 +1,94 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use sdl2::image::{InitFlag, LoadSurface, SaveSurface};
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::render::BlendMode;
+use sdl2::surface::Surface;
+
+use tiles::sheet;
+
+// Packs a directory of PNG frames into a sprite sheet and the atlas that goes with it, for
+// loading with parse_tiles. Frames named like "elf_m_run_anim_f0.png" become animations.
+fn run(args: &[String]) -> Result<(), String> {
+    if args.len() < 3 {
+        return Err("usage: spritesheet <frames dir> <out.png> <out.txt> \
+                    [--width N] [--padding N] [--props old.txt]"
+            .to_string());
+    }
+    let (dir, png, txt) = (&args[0], &args[1], &args[2]);
+    let mut width = None;
+    let mut padding = 0;
+    let mut props = Default::default();
+
+    let mut rest = args[3..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--width" => {
+                let w = rest.next().ok_or("--width needs a number of pixels")?;
+                width = Some(w.parse::<u32>().map_err(|e| e.to_string())?);
+            }
+            "--padding" => {
+                let p = rest.next().ok_or("--padding needs a number of pixels")?;
+                padding = p.parse::<u32>().map_err(|e| e.to_string())?;
+            }
+            // Keeps the properties, like solid, that were typed into an earlier atlas.
+            "--props" => {
+                let path = rest.next().ok_or("--props needs an atlas")?;
+                let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+                props = sheet::atlas_props(&text);
+            }
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+
+    let _image_context = sdl2::image::init(InitFlag::PNG)?;
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .map_err(|e| format!("{}: {}", dir, e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "png"))
+        .collect();
+    paths.sort();
+    if paths.is_empty() {
+        return Err(format!("{}: no png frames", dir));
+    }
+
+    let mut surfaces = Vec::new();
+    let mut frames = Vec::new();
+    for path in &paths {
+        let mut surface =
+            Surface::from_file(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        // Copied as they are, alpha and all, rather than blended onto the empty sheet.
+        surface.set_blend_mode(BlendMode::None)?;
+        let name = path.file_stem().unwrap().to_string_lossy().to_string();
+        frames.push((name, surface.width(), surface.height()));
+        surfaces.push(surface);
+    }
+
+    let sprites = sheet::group(&frames)?;
+    let sizes: Vec<(u32, u32)> = sprites.iter().map(sheet::Sprite::size).collect();
+    let width = width.unwrap_or_else(|| sheet::sheet_width(&sizes, padding));
+    let (places, height) = sheet::shelf_pack(&sizes, width, padding)?;
+
+    let mut out = Surface::new(width, height.max(1), PixelFormatEnum::RGBA32)?;
+    for (sprite, (x, y)) in sprites.iter().zip(&places) {
+        for (n, &frame) in sprite.frames.iter().enumerate() {
+            let to = Rect::new(
+                (x + sprite.w * n as u32) as i32,
+                *y as i32,
+                sprite.w,
+                sprite.h,
+            );
+            surfaces[frame].blit(None, &mut out, to)?;
+        }
+    }
+    out.save(Path::new(png))?;
+    fs::write(txt, sheet::atlas(&sprites, &places, &props)).map_err(|e| format!("{}: {}", txt, e))
+}
+
+fn main() -> Result<(), String> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    run(&args)
+}
@@ -0,0 +1,75 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use tiles::atlas::parse_tiles;
+use tiles::config::Assets;
+use tiles::lint;
+use tiles::map::Map;
+
+// Checks maps against the atlases and prints every problem found as
+//   map.json: $.tiles[12].tile: no tile named wal_mid
+// then fails if there were any, so it can run in CI.
+fn run(args: &[String]) -> Result<(), String> {
+    let mut atlases = Vec::new();
+    let mut maps = Vec::new();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--atlas" => atlases.push(rest.next().ok_or("--atlas needs a file")?.clone()),
+            _ if arg.starts_with("--") => return Err(format!("unknown argument: {}", arg)),
+            _ => maps.push(arg.clone()),
+        }
+    }
+    if maps.is_empty() {
+        return Err("usage: tiles-lint [--atlas <atlas.txt>]... <map.json>...".to_string());
+    }
+    // The game's own atlases unless told otherwise.
+    if atlases.is_empty() {
+        atlases = Assets::default()
+            .atlases
+            .into_iter()
+            .map(|atlas| atlas.list)
+            .collect();
+    }
+
+    let (mut tiles, mut anims) = (Default::default(), Default::default());
+    for atlas in &atlases {
+        if !Path::new(atlas).exists() {
+            return Err(format!("{}: no such atlas", atlas));
+        }
+        let (more_tiles, more_anims) = parse_tiles(atlas, atlas.clone())?;
+        Extend::extend(&mut tiles, more_tiles);
+        Extend::extend(&mut anims, more_anims);
+    }
+
+    let mut count = 0;
+    for path in &maps {
+        let map = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| serde_json::from_str::<Map>(&data).map_err(|e| e.to_string()));
+        let map = match map {
+            Ok(map) => map,
+            Err(e) => {
+                println!("{}: {}", path, e);
+                count += 1;
+                continue;
+            }
+        };
+        for problem in lint::lint(&map, &tiles, &anims) {
+            println!("{}: {}: {}", path, problem.path, problem.message);
+            count += 1;
+        }
+    }
+
+    match count {
+        0 => Ok(()),
+        1 => Err("1 problem".to_string()),
+        _ => Err(format!("{} problems", count)),
+    }
+}
+
+fn main() -> Result<(), String> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    run(&args)
+}
@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+// Steps a simulation at a fixed rate whatever rate frames are shown at. Each frame it is told
+// how much time went by and says how many steps that buys, carrying over what is left.
+pub struct Clock {
+    step: Duration,
+    owed: Duration,
+    // More steps than this in one frame and the rest of the time is dropped, so after a stall
+    // the game slows down for a moment instead of racing to catch up.
+    max_steps: u32,
+}
+
+impl Clock {
+    pub fn new(steps_per_second: u32, max_steps: u32) -> Clock {
+        Clock {
+            step: Duration::from_secs(1) / steps_per_second.max(1),
+            owed: Duration::from_secs(0),
+            max_steps,
+        }
+    }
+
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.owed += elapsed;
+        let mut steps = 0;
+        while self.owed >= self.step {
+            self.owed -= self.step;
+            steps += 1;
+            if steps == self.max_steps {
+                self.owed = Duration::from_secs(0);
+            }
+        }
+        steps
+    }
+}
+
+// How long to wait after a frame that took frame_time so frames come fps times a second. An
+// fps of 0 means as fast as possible.
+pub fn frame_wait(frame_time: Duration, fps: u32) -> Duration {
+    if fps == 0 {
+        return Duration::from_secs(0);
+    }
+    (Duration::from_secs(1) / fps)
+        .checked_sub(frame_time)
+        .unwrap_or_else(|| Duration::from_secs(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_at_a_fixed_rate() {
+        let mut clock = Clock::new(60, 5);
+        let frame = Duration::from_secs(1) / 60;
+        // Twice as many frames as steps, then half as many.
+        let steps: u32 = (0..120).map(|_| clock.advance(frame / 2)).sum();
+        assert_eq!(steps, 60);
+        assert_eq!(clock.advance(frame * 2), 2);
+
+        assert_eq!(clock.advance(Duration::from_secs(10)), 5);
+        assert_eq!(clock.advance(frame / 2), 0);
+    }
+
+    #[test]
+    fn waits_out_the_rest_of_the_frame() {
+        let frame = Duration::from_millis(20);
+        assert_eq!(
+            frame_wait(Duration::from_millis(5), 50),
+            Duration::from_millis(15)
+        );
+        assert_eq!(frame_wait(frame * 2, 50), Duration::from_secs(0));
+        assert_eq!(
+            frame_wait(Duration::from_millis(5), 0),
+            Duration::from_secs(0)
+        );
+    }
+}
@@ -0,0 +1,228 @@
+use cgmath::Vector2;
+
+use serde::{Deserialize, Serialize};
+
+use tiles::atlas::{AnimId, Animation, Animations};
+use tiles::ecs::{Components, Entities, Entity};
+use tiles::map::ProjectileKind;
+
+use crate::Dir;
+
+// Where something is. pos is its feet, in world units.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Transform {
+    pub pos: Vector2<f32>,
+    pub dir: Dir,
+}
+
+// What it looks like standing still and walking.
+#[derive(Clone, Copy)]
+pub struct Sprite {
+    pub idle: AnimId,
+    pub walk: AnimId,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Animator {
+    pub walking: bool,
+    pub frame: usize,
+    pub frame_counter: usize,
+    pub frame_rate: usize,
+}
+
+impl Animator {
+    pub fn new() -> Animator {
+        Animator {
+            walking: false,
+            frame: 0,
+            frame_counter: 0,
+            frame_rate: 3,
+        }
+    }
+
+    // The animation being played right now.
+    pub fn anim(&self, sprite: &Sprite) -> AnimId {
+        if self.walking {
+            sprite.walk
+        } else {
+            sprite.idle
+        }
+    }
+}
+
+// What decides where it goes.
+pub enum Brain {
+    Player,
+    // Walks straight at the player.
+    Chase,
+    // Stands still and can be talked to, see conversation.rs.
+    Talk(String),
+    // Does what scripts/<script>.rhai says, see behaviour.rs. state is the script's own.
+    Script { script: String, state: rhai::Map },
+}
+
+pub struct Ai {
+    pub brain: Brain,
+    pub speed: f32,
+}
+
+// The box projectiles hit, w by h world units centred on its feet. Spawned characters get
+// the size of their idle animation.
+pub struct Collider {
+    pub w: f32,
+    pub h: f32,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Health(pub f32);
+
+pub struct Shooter {
+    pub kind: ProjectileKind,
+    // Frames until it can fire again.
+    pub reload: u32,
+}
+
+// Particles it kicks up while walking, named in particles.json.
+pub struct Emitter(pub String);
+
+// Everything on a level. Characters are entities with whichever components they need:
+// scenery and items are only drawn, monsters also think, get hit and have health.
+#[derive(Default)]
+pub struct Characters {
+    pub entities: Entities,
+    // Only things something else has to find again are named, such as spawns that saves
+    // refer to and items that get picked up.
+    pub names: Components<String>,
+    pub transforms: Components<Transform>,
+    pub sprites: Components<Sprite>,
+    pub animators: Components<Animator>,
+    pub ais: Components<Ai>,
+    pub colliders: Components<Collider>,
+    pub healths: Components<Health>,
+    pub shooters: Components<Shooter>,
+    pub emitters: Components<Emitter>,
+}
+
+impl Characters {
+    // Something drawn with an animation. x, y is the top left corner in world units.
+    pub fn spawn_sprite(&mut self, anims: &Animations, x: f32, y: f32, sprite: Sprite) -> Entity {
+        let anim = &anims[sprite.idle];
+        let e = self.entities.spawn();
+        self.transforms.insert(
+            e,
+            Transform {
+                pos: Vector2::new(
+                    x + (((anim.w * 10) as f32) / 2.0),
+                    y + ((anim.h * 10) as f32),
+                ),
+                dir: Dir::Right,
+            },
+        );
+        self.sprites.insert(e, sprite);
+        self.animators.insert(e, Animator::new());
+        e
+    }
+
+    // A sprite that thinks, gets hit and can die.
+    pub fn spawn_character(
+        &mut self,
+        anims: &Animations,
+        x: f32,
+        y: f32,
+        sprite: Sprite,
+        ai: Ai,
+        health: f32,
+    ) -> Entity {
+        let e = self.spawn_sprite(anims, x, y, sprite);
+        self.colliders.insert(
+            e,
+            Collider {
+                w: (anims[sprite.idle].w * 10) as f32,
+                h: (anims[sprite.idle].h * 10) as f32,
+            },
+        );
+        self.ais.insert(e, ai);
+        self.healths.insert(e, Health(health));
+        e
+    }
+
+    pub fn despawn(&mut self, e: Entity) {
+        if self.entities.despawn(e) {
+            self.names.remove(e);
+            self.transforms.remove(e);
+            self.sprites.remove(e);
+            self.animators.remove(e);
+            self.ais.remove(e);
+            self.colliders.remove(e);
+            self.healths.remove(e);
+            self.shooters.remove(e);
+            self.emitters.remove(e);
+        }
+    }
+
+    // Moves an entity and all its components over to another level, where it gets a new id.
+    pub fn move_to(&mut self, e: Entity, other: &mut Characters) -> Entity {
+        let n = other.entities.spawn();
+        if let Some(c) = self.names.remove(e) {
+            other.names.insert(n, c);
+        }
+        if let Some(c) = self.transforms.remove(e) {
+            other.transforms.insert(n, c);
+        }
+        if let Some(c) = self.sprites.remove(e) {
+            other.sprites.insert(n, c);
+        }
+        if let Some(c) = self.animators.remove(e) {
+            other.animators.insert(n, c);
+        }
+        if let Some(c) = self.ais.remove(e) {
+            other.ais.insert(n, c);
+        }
+        if let Some(c) = self.colliders.remove(e) {
+            other.colliders.insert(n, c);
+        }
+        if let Some(c) = self.healths.remove(e) {
+            other.healths.insert(n, c);
+        }
+        if let Some(c) = self.shooters.remove(e) {
+            other.shooters.insert(n, c);
+        }
+        if let Some(c) = self.emitters.remove(e) {
+            other.emitters.insert(n, c);
+        }
+        self.despawn(e);
+        n
+    }
+
+    pub fn named(&self, name: &str) -> Option<Entity> {
+        self.names
+            .iter()
+            .find(|(_, n)| n.as_str() == name)
+            .map(|(e, _)| e)
+    }
+
+    pub fn pos(&self, e: Entity) -> Vector2<f32> {
+        self.transforms.get(e).unwrap().pos
+    }
+
+    // The animation an entity is showing right now and which frame of it.
+    pub fn anim<'n>(&self, anims: &'n Animations, e: Entity) -> (&'n Animation, usize) {
+        let animator = self.animators.get(e).unwrap();
+        (
+            &anims[animator.anim(self.sprites.get(e).unwrap())],
+            animator.frame,
+        )
+    }
+
+    // The top left corner of where an entity is drawn, and its size, in world units.
+    pub fn bounds(&self, anims: &Animations, e: Entity) -> (f32, f32, u32, u32) {
+        let (anim, _) = self.anim(anims, e);
+        let pos = self.pos(e);
+        (
+            pos.x - (anim.w as f32 / 2.0) * 10.0,
+            pos.y - (anim.h as f32) * 10.0,
+            anim.w * 10,
+            anim.h * 10,
+        )
+    }
+}
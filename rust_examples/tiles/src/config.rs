@@ -0,0 +1,398 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+// Everything about the game that can be changed without rebuilding it, loaded from
+// config.toml. Anything left out of the file keeps its default, and a missing file means all
+// defaults. Unknown keys are an error so typos don't go unnoticed.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub video: Video,
+    pub audio: Audio,
+    pub controls: Controls,
+    pub gameplay: Gameplay,
+    pub assets: Assets,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Video {
+    // The window's size when it opens. The game itself is always drawn at 1200x1200 and
+    // scaled to fit.
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: bool,
+    // "accelerated", falling back to software when it isn't available, or "software".
+    pub renderer: String,
+    pub vsync: bool,
+    // Frames shown a second without vsync, 0 for as many as possible.
+    pub fps: u32,
+    // The grid in world units the camera snaps to, 1 for screen pixels, 10 for art pixels.
+    pub pixel_snap: f32,
+}
+
+impl Default for Video {
+    fn default() -> Video {
+        Video {
+            width: 1200,
+            height: 1200,
+            fullscreen: false,
+            renderer: "accelerated".to_string(),
+            vsync: true,
+            fps: 60,
+            pixel_snap: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Audio {
+    pub frequency: i32,
+    // Samples mixed at a time. Smaller is quicker to respond but can crackle.
+    pub chunk_size: i32,
+    // Sounds that can play at once.
+    pub channels: i32,
+    pub music: String,
+    // 0 to 128.
+    pub music_volume: i32,
+    pub sound_volume: i32,
+    // Played when the player picks something up, and when a monster reaches them.
+    pub pickup_sound: String,
+    pub caught_sound: String,
+}
+
+impl Default for Audio {
+    fn default() -> Audio {
+        Audio {
+            frequency: 44_100,
+            chunk_size: 1_024,
+            channels: 4,
+            music: "music.mp3".to_string(),
+            music_volume: 128,
+            sound_volume: 128,
+            pickup_sound: "coin.wav".to_string(),
+            caught_sound: "coin.wav".to_string(),
+        }
+    }
+}
+
+// Key names as SDL knows them, such as "W", "Space" or "Left Shift".
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Controls {
+    pub up: String,
+    pub down: String,
+    pub left: String,
+    pub right: String,
+    pub fire: String,
+    pub interact: String,
+    pub pause: String,
+    pub fullscreen: String,
+    pub debug: String,
+    // Outlines where nothing can walk.
+    pub show_forbidden: String,
+    pub quick_save: String,
+    pub quick_load: String,
+}
+
+impl Default for Controls {
+    fn default() -> Controls {
+        Controls {
+            up: "W".to_string(),
+            down: "S".to_string(),
+            left: "A".to_string(),
+            right: "D".to_string(),
+            fire: "Space".to_string(),
+            interact: "E".to_string(),
+            pause: "Escape".to_string(),
+            fullscreen: "F11".to_string(),
+            debug: "F3".to_string(),
+            show_forbidden: "P".to_string(),
+            quick_save: "F5".to_string(),
+            quick_load: "F9".to_string(),
+        }
+    }
+}
+
+impl Controls {
+    // Each action with its key, in the order they are listed above.
+    pub fn keys(&self) -> Vec<(&'static str, &str)> {
+        vec![
+            ("up", &self.up),
+            ("down", &self.down),
+            ("left", &self.left),
+            ("right", &self.right),
+            ("fire", &self.fire),
+            ("interact", &self.interact),
+            ("pause", &self.pause),
+            ("fullscreen", &self.fullscreen),
+            ("debug", &self.debug),
+            ("show_forbidden", &self.show_forbidden),
+            ("quick_save", &self.quick_save),
+            ("quick_load", &self.quick_load),
+        ]
+    }
+}
+
+// Replays only come out the same when played back with the same gameplay settings, so
+// recordings carry their own copy.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Gameplay {
+    // The level a new game starts on.
+    pub level: String,
+    // World units a frame.
+    pub player_speed: f32,
+    // Every monster's speed from the map is multiplied by this.
+    pub monster_speed: f32,
+    // Frames each frame of an animation is shown for.
+    pub animation_frames: usize,
+}
+
+impl Default for Gameplay {
+    fn default() -> Gameplay {
+        Gameplay {
+            level: "map".to_string(),
+            player_speed: 13.0,
+            monster_speed: 1.0,
+            animation_frames: 3,
+        }
+    }
+}
+
+// A sprite sheet and the list of what is where on it, see atlas.rs. Tiles name the sheet they
+// come from, so maps keep working when an atlas is added.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Atlas {
+    pub name: String,
+    pub list: String,
+    pub image: String,
+}
+
+impl Atlas {
+    fn new(name: &str, list: &str, image: &str) -> Atlas {
+        Atlas {
+            name: name.to_string(),
+            list: list.to_string(),
+            image: image.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Assets {
+    // Where the game's files are. Every other asset path is relative to it.
+    pub dir: String,
+    // An asset pack next to the executable, whose files are used over loose ones. Empty for
+    // none.
+    pub pack: String,
+    pub particles: String,
+    pub font: String,
+    pub font_letters: String,
+    pub atlases: Vec<Atlas>,
+}
+
+impl Default for Assets {
+    fn default() -> Assets {
+        Assets {
+            dir: ".".to_string(),
+            pack: "assets.pack".to_string(),
+            particles: "particles.json".to_string(),
+            font: "crossfont.png".to_string(),
+            font_letters: "crossfont.txt".to_string(),
+            atlases: vec![
+                Atlas::new("generic", "tiles_list.txt", "tiles.png"),
+                Atlas::new("slamslime", "slamslime.txt", "slamslime.png"),
+            ],
+        }
+    }
+}
+
+// Sets a dotted key such as video.fps in a parsed file. The value is read as TOML if it can
+// be, so numbers and booleans work, and as a plain string otherwise.
+fn set(table: &mut toml::Value, key: &str, value: &str) -> Result<(), String> {
+    let value = match toml::from_str::<toml::Value>(&format!("v = {}", value)) {
+        Ok(toml::Value::Table(mut t)) => t.remove("v").unwrap(),
+        _ => toml::Value::String(value.to_string()),
+    };
+    let mut parts: Vec<&str> = key.split('.').collect();
+    let last = parts.pop().filter(|p| !p.is_empty());
+    let last = last.ok_or_else(|| format!("{}: expected a key like video.fps", key))?;
+    let mut table = table;
+    for part in parts {
+        table = match table {
+            toml::Value::Table(t) => t
+                .entry(part.to_string())
+                .or_insert_with(|| toml::Value::Table(toml::value::Table::new())),
+            _ => return Err(format!("{}: {} isn't a section", key, part)),
+        };
+    }
+    match table {
+        toml::Value::Table(t) => {
+            t.insert(last.to_string(), value);
+            Ok(())
+        }
+        _ => Err(format!("{}: not a section", key)),
+    }
+}
+
+impl Config {
+    // Reads the file if there is one, applies overrides given as (dotted key, value) and
+    // checks the result.
+    pub fn load(path: &str, overrides: &[(String, String)]) -> Result<Config, String> {
+        let text = if Path::new(path).exists() {
+            fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?
+        } else {
+            String::new()
+        };
+        Config::parse(&text, overrides).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn parse(text: &str, overrides: &[(String, String)]) -> Result<Config, String> {
+        let mut table: toml::Value = toml::from_str(text).map_err(|e| e.to_string())?;
+        for (key, value) in overrides {
+            set(&mut table, key, value)?;
+        }
+        let config: Config = table.try_into().map_err(|e| e.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Every problem at once, one per line, so they can all be fixed in one go.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut problems = Vec::new();
+        let mut check = |ok: bool, problem: &str| {
+            if !ok {
+                problems.push(problem.to_string());
+            }
+        };
+
+        let v = &self.video;
+        check(
+            v.width > 0 && v.height > 0,
+            "video.width and video.height must be above 0",
+        );
+        check(
+            v.renderer == "accelerated" || v.renderer == "software",
+            "video.renderer must be \"accelerated\" or \"software\"",
+        );
+        check(v.pixel_snap >= 0.0, "video.pixel_snap can't be negative");
+
+        let a = &self.audio;
+        check(
+            a.frequency >= 8_000 && a.frequency <= 192_000,
+            "audio.frequency must be between 8000 and 192000",
+        );
+        check(
+            a.chunk_size >= 256 && a.chunk_size <= 8_192 && (a.chunk_size as u32).is_power_of_two(),
+            "audio.chunk_size must be a power of two from 256 to 8192",
+        );
+        check(
+            a.channels >= 1 && a.channels <= 64,
+            "audio.channels must be between 1 and 64",
+        );
+        check(
+            (0..=128).contains(&a.music_volume) && (0..=128).contains(&a.sound_volume),
+            "audio.music_volume and audio.sound_volume must be between 0 and 128",
+        );
+
+        let mut seen = HashSet::new();
+        for (action, key) in self.controls.keys() {
+            if key.is_empty() {
+                check(false, &format!("controls.{} has no key", action));
+            } else if !seen.insert(key.to_lowercase()) {
+                check(
+                    false,
+                    &format!("controls.{} uses {}, which is already taken", action, key),
+                );
+            }
+        }
+
+        let mut names = HashSet::new();
+        for atlas in &self.assets.atlases {
+            check(
+                names.insert(&atlas.name),
+                &format!("assets.atlases has two called {}", atlas.name),
+            );
+        }
+        check(
+            !self.assets.atlases.is_empty(),
+            "assets.atlases needs at least one atlas",
+        );
+
+        let g = &self.gameplay;
+        check(!g.level.is_empty(), "gameplay.level can't be empty");
+        check(
+            g.player_speed > 0.0,
+            "gameplay.player_speed must be above 0",
+        );
+        check(
+            g.monster_speed >= 0.0,
+            "gameplay.monster_speed can't be negative",
+        );
+        check(
+            g.animation_frames > 0,
+            "gameplay.animation_frames must be at least 1",
+        );
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("\n"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_file_matches_defaults() {
+        let config = Config::load("config.toml", &[]).unwrap();
+        let defaults = Config::default();
+        assert_eq!(config.video.fps, defaults.video.fps);
+        assert_eq!(config.audio.music, defaults.audio.music);
+        assert_eq!(config.controls.keys(), defaults.controls.keys());
+        assert_eq!(config.gameplay.player_speed, defaults.gameplay.player_speed);
+        assert_eq!(config.assets.particles, defaults.assets.particles);
+        assert_eq!(config.assets.pack, defaults.assets.pack);
+        assert_eq!(config.assets.atlases, defaults.assets.atlases);
+        assert_eq!(config.audio.pickup_sound, defaults.audio.pickup_sound);
+    }
+
+    #[test]
+    fn overrides_win_over_the_file() {
+        let text = "[video]\nfps = 30\n";
+        let overrides = vec![
+            ("video.vsync".to_string(), "false".to_string()),
+            ("gameplay.level".to_string(), "dungeon".to_string()),
+        ];
+        let config = Config::parse(text, &overrides).unwrap();
+        assert_eq!(config.video.fps, 30);
+        assert!(!config.video.vsync);
+        assert_eq!(config.gameplay.level, "dungeon");
+        assert_eq!(config.video.width, 1200);
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let err = Config::parse("[video]\nfsp = 30\n", &[]).err().unwrap();
+        assert!(err.contains("unknown field `fsp`"), "{}", err);
+
+        let text = "[audio]\nchunk_size = 1000\n[controls]\nfire = \"E\"\nquick_save = \"p\"\n";
+        let err = Config::parse(text, &[]).err().unwrap();
+        assert_eq!(
+            err,
+            "audio.chunk_size must be a power of two from 256 to 8192\n\
+             controls.interact uses E, which is already taken\n\
+             controls.quick_save uses p, which is already taken"
+        );
+    }
+}
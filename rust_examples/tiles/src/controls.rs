@@ -0,0 +1,42 @@
+use sdl2::keyboard::Keycode;
+
+use tiles::config::Controls;
+
+// The keys from the controls section of the config, looked up once at startup.
+pub struct Keys {
+    pub up: Keycode,
+    pub down: Keycode,
+    pub left: Keycode,
+    pub right: Keycode,
+    pub fire: Keycode,
+    pub interact: Keycode,
+    pub pause: Keycode,
+    pub fullscreen: Keycode,
+    pub debug: Keycode,
+    pub show_forbidden: Keycode,
+    pub quick_save: Keycode,
+    pub quick_load: Keycode,
+}
+
+impl Keys {
+    pub fn from_config(controls: &Controls) -> Result<Keys, String> {
+        let key = |action: &str, name: &str| {
+            Keycode::from_name(name)
+                .ok_or_else(|| format!("controls.{}: no key named {}", action, name))
+        };
+        Ok(Keys {
+            up: key("up", &controls.up)?,
+            down: key("down", &controls.down)?,
+            left: key("left", &controls.left)?,
+            right: key("right", &controls.right)?,
+            fire: key("fire", &controls.fire)?,
+            interact: key("interact", &controls.interact)?,
+            pause: key("pause", &controls.pause)?,
+            fullscreen: key("fullscreen", &controls.fullscreen)?,
+            debug: key("debug", &controls.debug)?,
+            show_forbidden: key("show_forbidden", &controls.show_forbidden)?,
+            quick_save: key("quick_save", &controls.quick_save)?,
+            quick_load: key("quick_load", &controls.quick_load)?,
+        })
+    }
+}
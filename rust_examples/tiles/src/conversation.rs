@@ -0,0 +1,250 @@
+use std::collections::BTreeSet;
+
+use cgmath::InnerSpace;
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget};
+
+use tiles::dialogue::{Choice, Condition, Dialogue, DialogueAction, Node};
+
+use crate::components::Brain;
+use crate::inventory::Inventory;
+use crate::text::Font;
+use crate::{GameEvent, PlayerInput, World, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// How close the player has to be to an NPC to talk to them, in world units.
+const TALK_RANGE: f32 = 250.0;
+
+// Letters of a line revealed each frame.
+const TYPE_SPEED: usize = 1;
+
+const BOX_HEIGHT: u32 = 320;
+const SCALE: u32 = 4;
+const LINE: i32 = 40;
+const LINE_LETTERS: usize = 38;
+
+// A conversation in progress. It lives in the world and only moves on with the input for a
+// frame, so replays play conversations out the same way.
+pub struct Conversation {
+    dialogue: Dialogue,
+    node: String,
+    // Letters of the node's text shown so far.
+    shown: usize,
+    // Which of the offered choices is picked.
+    selected: usize,
+    // Up and down as they were last frame, so holding one moves the selection only once.
+    held: (bool, bool),
+}
+
+impl Conversation {
+    fn node(&self) -> &Node {
+        &self.dialogue.nodes[&self.node]
+    }
+}
+
+fn holds(conditions: &[Condition], flags: &BTreeSet<String>, inventory: &Inventory) -> bool {
+    conditions.iter().all(|c| match c {
+        Condition::Flag { flag } => flags.contains(flag),
+        Condition::NotFlag { flag } => !flags.contains(flag),
+        Condition::HasItem { item } => inventory.count(item) > 0,
+    })
+}
+
+// The choices whose conditions hold.
+fn offered<'d>(node: &'d Node, flags: &BTreeSet<String>, inventory: &Inventory) -> Vec<&'d Choice> {
+    node.choices
+        .iter()
+        .filter(|c| holds(&c.conditions, flags, inventory))
+        .collect()
+}
+
+impl<'a> World<'a> {
+    // Starts talking to the nearest NPC in range, if there is one with something to say.
+    pub fn start_conversation(&mut self) -> Result<bool, String> {
+        let player_pos = self.characters.pos(self.player);
+        let npc = self
+            .characters
+            .ais
+            .iter()
+            .filter_map(|(e, ai)| match &ai.brain {
+                Brain::Talk(dialogue) => Some((e, dialogue)),
+                _ => None,
+            })
+            .map(|(e, d)| ((self.characters.pos(e) - player_pos).magnitude2(), e, d))
+            .filter(|(d2, _, _)| *d2 < TALK_RANGE * TALK_RANGE)
+            .min_by(|(d1, e1, _), (d2, e2, _)| {
+                d1.partial_cmp(d2)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then(e1.cmp(e2))
+            })
+            .map(|(_, _, d)| d.clone());
+        let dialogue = match npc {
+            Some(name) => Dialogue::load(&name)?,
+            None => return Ok(false),
+        };
+
+        let start = dialogue
+            .starts
+            .iter()
+            .find(|s| holds(&s.conditions, &self.flags, &self.inventory))
+            .map(|s| s.next.clone());
+        if start.is_none() {
+            return Ok(false);
+        }
+        self.conversation = Some(Conversation {
+            dialogue,
+            node: String::new(),
+            shown: 0,
+            selected: 0,
+            held: (false, false),
+        });
+        self.go_to(start);
+        Ok(true)
+    }
+
+    // Moves on to a node and runs its actions, or ends the conversation.
+    fn go_to(&mut self, next: Option<String>) {
+        let c = match (&mut self.conversation, next) {
+            (Some(c), Some(next)) => {
+                c.node = next;
+                c.shown = 0;
+                c.selected = 0;
+                c
+            }
+            _ => {
+                self.conversation = None;
+                return;
+            }
+        };
+
+        for action in &c.dialogue.nodes[&c.node].actions {
+            match action {
+                DialogueAction::SetFlag { flag } => {
+                    self.flags.insert(flag.clone());
+                }
+                DialogueAction::ClearFlag { flag } => {
+                    self.flags.remove(flag);
+                }
+                DialogueAction::Give { item, count } => self.inventory.add(item, *count),
+                DialogueAction::Take { item } => {
+                    self.inventory.take(item);
+                }
+                DialogueAction::Score { points } => self.score += points,
+                DialogueAction::Sound { sound } => {
+                    self.events.publish(GameEvent::Sound(sound.clone()))
+                }
+            }
+        }
+    }
+
+    // Types out the current line, then lets the player move between the choices with up and
+    // down and carry on with interact. Interact while typing shows the whole line at once.
+    pub fn update_conversation(&mut self, pi: &PlayerInput) {
+        let c = match &mut self.conversation {
+            Some(c) => c,
+            None => return,
+        };
+        let up = pi.up && !c.held.0;
+        let down = pi.down && !c.held.1;
+        c.held = (pi.up, pi.down);
+
+        let len = c.node().text.chars().count();
+        if c.shown < len {
+            c.shown = if pi.interact {
+                len
+            } else {
+                (c.shown + TYPE_SPEED).min(len)
+            };
+            return;
+        }
+
+        let choices = offered(c.node(), &self.flags, &self.inventory).len();
+        if up && c.selected > 0 {
+            c.selected -= 1;
+        }
+        if down && c.selected + 1 < choices {
+            c.selected += 1;
+        }
+        if pi.interact {
+            let next = match offered(c.node(), &self.flags, &self.inventory).get(c.selected) {
+                Some(choice) => choice.next.clone(),
+                None => c.node().next.clone(),
+            };
+            self.go_to(next);
+        }
+    }
+}
+
+// Splits text into lines of at most LINE_LETTERS letters, breaking between words.
+fn wrap(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > LINE_LETTERS {
+            lines.push(line);
+            line = String::new();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+// The text box along the bottom of the screen: who is talking, what has been typed out so far
+// and, once it all has, the choices with the picked one highlighted.
+pub fn draw_conversation<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    font: &Font,
+    world: &World,
+) -> Result<(), String> {
+    let c = match &world.conversation {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+    let node = c.node();
+    let top = (SCREEN_HEIGHT - BOX_HEIGHT) as i32;
+
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.fill_rect(Rect::new(0, top, SCREEN_WIDTH, BOX_HEIGHT))?;
+    canvas.set_draw_color(Color::RGB(200, 200, 200));
+    canvas.draw_rect(Rect::new(4, top + 4, SCREEN_WIDTH - 8, BOX_HEIGHT - 8))?;
+
+    let mut y = top + 20;
+    if !node.speaker.is_empty() {
+        font.draw(canvas, &node.speaker, 20, y, SCALE)?;
+        y += LINE + 10;
+    }
+
+    // Lines are wrapped as if fully typed out so words don't jump between lines as they
+    // appear.
+    let mut left = c.shown;
+    for line in wrap(&node.text) {
+        let shown: String = line.chars().take(left).collect();
+        font.draw(canvas, &shown, 40, y, SCALE)?;
+        y += LINE;
+        left = left.saturating_sub(line.chars().count() + 1);
+    }
+
+    if c.shown < node.text.chars().count() {
+        return Ok(());
+    }
+    y += 10;
+    for (i, choice) in offered(node, &world.flags, &world.inventory)
+        .iter()
+        .enumerate()
+    {
+        if i == c.selected {
+            canvas.set_draw_color(Color::RGB(70, 70, 110));
+            canvas.fill_rect(Rect::new(30, y - 6, SCREEN_WIDTH - 60, LINE as u32))?;
+        }
+        font.draw(canvas, &choice.text, 60, y, SCALE)?;
+        y += LINE;
+    }
+    Ok(())
+}
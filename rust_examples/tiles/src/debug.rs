@@ -0,0 +1,256 @@
+use std::time::{Duration, Instant};
+
+use cgmath::InnerSpace;
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, RenderTarget};
+
+use tiles::ecs::Entity;
+use tiles::event::Subscriber;
+
+use crate::components::Brain;
+use crate::particle::Particles;
+use crate::text::Font;
+use crate::{Camera, DebugState, GameEvent, World, FIRING_RANGE, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// Frames kept for the frame time graph, two seconds worth.
+const GRAPH_FRAMES: usize = 120;
+
+const LOG_LINES: usize = 8;
+const EVENT_LINES: usize = 6;
+
+const SCALE: u32 = 3;
+const LINE: i32 = 26;
+const PANEL_W: u32 = 440;
+
+// What a character is up to, worked out the same way World::think decides how it moves.
+fn ai_state(e: Entity, world: &World) -> &'static str {
+    let brain = match world.characters.ais.get(e) {
+        Some(ai) => &ai.brain,
+        None => return "scenery",
+    };
+    let to_player = world.characters.pos(world.player) - world.characters.pos(e);
+    let d2 = to_player.magnitude2();
+    match brain {
+        Brain::Player => "player",
+        Brain::Talk(_) => "npc",
+        _ if d2 < 5.0 => "caught player",
+        Brain::Script { .. } => "scripted",
+        Brain::Chase
+            if world.characters.shooters.contains(e) && d2 < FIRING_RANGE * FIRING_RANGE =>
+        {
+            "shooting"
+        }
+        Brain::Chase => "chasing",
+    }
+}
+
+impl DebugState {
+    // Called once a frame, after the frame has been shown and waited for.
+    pub fn end_frame(&mut self) {
+        let now = Instant::now();
+        self.frame_times.push_back(now - self.last_frame);
+        self.last_frame = now;
+        if self.frame_times.len() > GRAPH_FRAMES {
+            self.frame_times.pop_front();
+        }
+    }
+
+    // Prints a line to stderr and keeps the last few for the overlay.
+    pub fn log(&mut self, line: String) {
+        eprintln!("{}", line);
+        self.log.push_back(line);
+        if self.log.len() > LOG_LINES {
+            self.log.pop_front();
+        }
+    }
+
+    // Picks the character drawn at a point on the screen for the inspector. When sprites
+    // overlap the one in front wins, clicking empty floor clears the selection.
+    pub fn select_at(&mut self, world: &World, camera: &Camera, x: i32, y: i32) {
+        let (wx, wy) = camera.to_world(x, y);
+        let characters = &world.characters;
+        self.selected = characters
+            .sprites
+            .iter()
+            .map(|(e, _)| e)
+            .filter(|e| {
+                let (left, top, w, h) = characters.bounds(world.anims, *e);
+                wx >= left && wx < left + w as f32 && wy >= top && wy < top + h as f32
+            })
+            .max_by(|e1, e2| {
+                characters
+                    .pos(*e1)
+                    .y
+                    .partial_cmp(&characters.pos(*e2).y)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then(e2.cmp(e1))
+            });
+    }
+
+    fn fps(&self) -> (f32, f32) {
+        if self.frame_times.is_empty() {
+            return (0.0, 0.0);
+        }
+        let total: Duration = self.frame_times.iter().sum();
+        let ms = total.as_secs_f32() * 1000.0 / self.frame_times.len() as f32;
+        (1000.0 / ms, ms)
+    }
+
+    fn inspector(&self, world: &World) -> Vec<String> {
+        let e = match self.selected {
+            Some(e) => e,
+            None => return vec!["click someone to inspect".to_string()],
+        };
+        let characters = &world.characters;
+        if !characters.entities.is_alive(e) {
+            return vec![format!("{} is gone", e)];
+        }
+        let animator = characters.animators.get(e).unwrap();
+        let anim = animator.anim(characters.sprites.get(e).unwrap());
+        let pos = characters.pos(e);
+        let mut lines = vec![
+            match characters.names.get(e) {
+                Some(name) => format!("{} {}", name, e),
+                None => e.to_string(),
+            },
+            format!("pos {} {}", pos.x as i32, pos.y as i32),
+            format!("anim {}", world.anims.name(anim).replace('_', " ")),
+            format!("frame {}", animator.frame),
+        ];
+        if let Some(ai) = characters.ais.get(e) {
+            lines.push(format!("speed {}", ai.speed));
+        }
+        if let Some(health) = characters.healths.get(e) {
+            lines.push(format!("health {}", health.0.ceil()));
+        }
+        lines.push(format!("ai {}", ai_state(e, world)));
+        lines
+    }
+
+    // Timings, counts, the inspector and recent events down the right, the log along the
+    // bottom.
+    pub fn draw_overlay<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        font: &Font,
+        world: &World,
+        particles: &Particles,
+        camera: &Camera,
+    ) -> Result<(), String> {
+        let (fps, ms) = self.fps();
+        let x = (SCREEN_WIDTH - PANEL_W) as i32;
+
+        let mut lines = vec![
+            format!("fps {}", fps.round()),
+            format!("frame {:.1} ms", ms),
+        ];
+        let graph_top = 10 + LINE * lines.len() as i32;
+        let graph_h = 100;
+        lines.extend(vec![String::new(); 5]);
+        lines.extend(vec![
+            format!("entities {}", world.characters.entities.len()),
+            format!("projectiles {}", world.projectiles.len()),
+            format!("particles {}", particles.len()),
+            format!("camera {} {}", camera.x as i32, camera.y as i32),
+            String::new(),
+        ]);
+        lines.extend(self.inspector(world));
+        lines.push(String::new());
+        for (event, count) in &self.events {
+            lines.push(match count {
+                1 => event.clone(),
+                _ => format!("{} x{}", event, count),
+            });
+        }
+
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 180));
+        canvas.fill_rect(Rect::new(
+            x - 10,
+            0,
+            PANEL_W + 10,
+            (20 + LINE * lines.len() as i32) as u32,
+        ))?;
+
+        // One bar per frame, with a line where a frame takes as long as it should at 60 fps.
+        let target_ms = 1000.0 / crate::FPS as f32;
+        for (i, t) in self.frame_times.iter().enumerate() {
+            let t = t.as_secs_f32() * 1000.0;
+            let h = ((t / (target_ms * 2.0)) * graph_h as f32).min(graph_h as f32) as u32;
+            canvas.set_draw_color(if t > target_ms * 1.2 {
+                Color::RGB(255, 80, 80)
+            } else {
+                Color::RGB(80, 255, 80)
+            });
+            canvas.fill_rect(Rect::new(
+                x + i as i32 * 3,
+                graph_top + graph_h - h as i32,
+                2,
+                h.max(1),
+            ))?;
+        }
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        canvas.draw_line(
+            (x, graph_top + graph_h / 2),
+            (x + (GRAPH_FRAMES * 3) as i32, graph_top + graph_h / 2),
+        )?;
+
+        for (i, line) in lines.iter().enumerate() {
+            font.draw(canvas, line, x, 10 + LINE * i as i32, SCALE)?;
+        }
+
+        if !self.log.is_empty() {
+            let top = SCREEN_HEIGHT as i32 - 100 - LINE * self.log.len() as i32;
+            canvas.set_draw_color(Color::RGBA(0, 0, 0, 180));
+            canvas.fill_rect(Rect::new(
+                0,
+                top - 10,
+                SCREEN_WIDTH,
+                (LINE * self.log.len() as i32 + 10) as u32,
+            ))?;
+            for (i, line) in self.log.iter().enumerate() {
+                font.draw(canvas, line, 20, top + LINE * i as i32, SCALE)?;
+            }
+        }
+        canvas.set_blend_mode(BlendMode::None);
+
+        Ok(())
+    }
+}
+
+// A short line for the overlay.
+fn describe(event: &GameEvent) -> String {
+    match event {
+        GameEvent::EnemyReachedPlayer(e) => format!("{} reached player", e),
+        GameEvent::ItemPicked { item, .. } => format!("picked {}", item.replace('_', " ")),
+        GameEvent::CharacterDied {
+            name: Some(name), ..
+        } => format!("{} died", name),
+        GameEvent::CharacterDied { name: None, .. } => "something died".to_string(),
+        GameEvent::TriggerEntered(i) => format!("entered trigger {}", i),
+        GameEvent::TriggerLeft(i) => format!("left trigger {}", i),
+        GameEvent::ProjectileHit { target, .. } => format!("hit {}", target),
+        GameEvent::Sound(sound) => format!("sound {}", sound),
+        GameEvent::Message(_) => "message".to_string(),
+    }
+}
+
+// Keeps the latest events for the overlay. The same event again, such as a monster standing on
+// the player, is counted up instead of filling the list.
+impl Subscriber<GameEvent> for DebugState {
+    fn notify(&mut self, event: &GameEvent) -> Result<(), String> {
+        let line = describe(event);
+        match self.events.back_mut() {
+            Some((last, count)) if *last == line => *count += 1,
+            _ => {
+                self.events.push_back((line, 1));
+                if self.events.len() > EVENT_LINES {
+                    self.events.pop_front();
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::assets;
+
+// A conversation with an NPC, loaded from dialogue/<name>.json. It opens at the first of
+// starts whose conditions hold and moves from node to node as the player picks choices.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Dialogue {
+    pub starts: Vec<Branch>,
+    pub nodes: HashMap<String, Node>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Branch {
+    pub next: String,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+}
+
+// One thing said. Its actions run when it is reached. With no choices, carrying on goes to
+// next, or ends the conversation if there is none.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Node {
+    #[serde(default)]
+    pub speaker: String,
+    pub text: String,
+    #[serde(default)]
+    pub actions: Vec<DialogueAction>,
+    #[serde(default)]
+    pub choices: Vec<Choice>,
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+// A reply the player can pick, only offered while its conditions hold. No next ends the
+// conversation.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Choice {
+    pub text: String,
+    #[serde(default)]
+    pub next: Option<String>,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "if", rename_all = "snake_case")]
+pub enum Condition {
+    Flag { flag: String },
+    NotFlag { flag: String },
+    HasItem { item: String },
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum DialogueAction {
+    SetFlag { flag: String },
+    ClearFlag { flag: String },
+    Give { item: String, count: u32 },
+    // Uses up one of the item.
+    Take { item: String },
+    Score { points: u32 },
+    Sound { sound: String },
+}
+
+impl Dialogue {
+    pub fn load(name: &str) -> Result<Dialogue, String> {
+        let path = format!("dialogue/{}.json", name);
+        let data = assets::read_to_string(&path)?;
+        let dialogue: Dialogue =
+            serde_json::from_str(&data).map_err(|e| format!("{}: {}", path, e))?;
+        dialogue.check().map_err(|e| format!("{}: {}", path, e))?;
+        Ok(dialogue)
+    }
+
+    // Every node that is pointed at has to exist, and there has to be a way in.
+    pub fn check(&self) -> Result<(), String> {
+        if self.starts.is_empty() {
+            return Err("no starts".to_string());
+        }
+        let missing = |next: &String| {
+            if self.nodes.contains_key(next) {
+                Ok(())
+            } else {
+                Err(format!("no node named {}", next))
+            }
+        };
+        for start in &self.starts {
+            missing(&start.next)?;
+        }
+        for node in self.nodes.values() {
+            for next in node
+                .next
+                .iter()
+                .chain(node.choices.iter().flat_map(|c| &c.next))
+            {
+                missing(next)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn npc_dialogue_loads() {
+        let dialogue = Dialogue::load("old_man").unwrap();
+        assert!(dialogue.nodes.contains_key("hello"));
+    }
+
+    #[test]
+    fn missing_nodes_are_caught() {
+        let mut dialogue = Dialogue::load("old_man").unwrap();
+        dialogue.nodes.remove("hello");
+        assert!(dialogue.check().is_err());
+    }
+}
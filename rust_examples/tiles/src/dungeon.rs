@@ -0,0 +1,377 @@
+use rand::prelude::*;
+
+use crate::autotile;
+use crate::grid::{Grid, CELL};
+use crate::map::{Map, MapAnimation, MapEmitter, MapEntry, MapSpawn};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Style {
+    // Rectangular rooms joined by one cell wide corridors.
+    Rooms,
+    // Smoothed random noise, cellular automata style.
+    Caves,
+}
+
+// Monsters that fit in a single cell, as (idle, walk, speed).
+const MONSTERS: &[(&str, &str, f32)] = &[
+    ("goblin_idle_anim", "goblin_run_anim", 10.0),
+    ("imp_idle_anim", "imp_run_anim", 9.0),
+    ("skelet_idle_anim", "skelet_run_anim", 6.0),
+    ("tiny_zombie_idle_anim", "tiny_zombie_run_anim", 7.0),
+    ("zombie_idle_anim", "zombie_run_anim", 8.0),
+    ("muddy_idle_anim", "muddy_run_anim", 4.0),
+];
+
+struct Room {
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+}
+
+impl Room {
+    fn center(&self) -> (i32, i32) {
+        (self.x + self.w / 2, self.y + self.h / 2)
+    }
+
+    fn overlaps(&self, other: &Room) -> bool {
+        // Keep at least one cell of wall between rooms.
+        self.x - 1 <= other.x + other.w
+            && other.x - 1 <= self.x + self.w
+            && self.y - 1 <= other.y + other.h
+            && other.y - 1 <= self.y + self.h
+    }
+}
+
+fn carve_rooms(grid: &mut Grid, rng: &mut StdRng) -> Vec<Room> {
+    let mut rooms: Vec<Room> = Vec::new();
+    let wanted = ((grid.w * grid.h) / 80).max(2);
+
+    for _ in 0..200 {
+        if rooms.len() as i32 >= wanted {
+            break;
+        }
+        let w = rng.gen_range(4, 10.min(grid.w - 2));
+        let h = rng.gen_range(4, 8.min(grid.h - 2));
+        let room = Room {
+            x: rng.gen_range(1, grid.w - w),
+            y: rng.gen_range(1, grid.h - h),
+            w,
+            h,
+        };
+        if rooms.iter().any(|r| r.overlaps(&room)) {
+            continue;
+        }
+
+        for y in room.y..room.y + room.h {
+            for x in room.x..room.x + room.w {
+                grid.set(x, y, false);
+            }
+        }
+
+        // Join each room to the one before it, which keeps the whole dungeon connected.
+        if let Some(prev) = rooms.last() {
+            let (x1, y1) = prev.center();
+            let (x2, y2) = room.center();
+            let corner = if rng.gen_bool(0.5) {
+                (x2, y1)
+            } else {
+                (x1, y2)
+            };
+            carve_line(grid, (x1, y1), corner);
+            carve_line(grid, corner, (x2, y2));
+        }
+
+        rooms.push(room);
+    }
+
+    rooms
+}
+
+fn carve_line(grid: &mut Grid, from: (i32, i32), to: (i32, i32)) {
+    let (mut x, mut y) = from;
+    loop {
+        grid.set(x, y, false);
+        if (x, y) == to {
+            break;
+        }
+        x += (to.0 - x).signum();
+        y += (to.1 - y).signum();
+    }
+}
+
+fn carve_caves(grid: &mut Grid, rng: &mut StdRng) {
+    for y in 1..grid.h - 1 {
+        for x in 1..grid.w - 1 {
+            grid.set(x, y, rng.gen_bool(0.45));
+        }
+    }
+
+    for _ in 0..5 {
+        let mut next = grid.clone();
+        for y in 1..grid.h - 1 {
+            for x in 1..grid.w - 1 {
+                // Walls survive with 4 walls around them, floor turns to wall with 5.
+                let n = grid.solid_neighbours(x, y);
+                next.set(x, y, n >= 5 || (n == 4 && grid.solid(x, y)));
+            }
+        }
+        *grid = next;
+    }
+
+    // Only keep the biggest open area so every part of the cave can be walked to.
+    let mut best: Vec<bool> = Vec::new();
+    let mut best_count = 0;
+    let mut claimed = vec![false; (grid.w * grid.h) as usize];
+    for (x, y) in grid.floor_cells() {
+        if claimed[(y * grid.w + x) as usize] {
+            continue;
+        }
+        let region = grid.reachable(x, y);
+        let count = region.iter().filter(|r| **r).count();
+        for (i, r) in region.iter().enumerate() {
+            claimed[i] |= *r;
+        }
+        if count > best_count {
+            best_count = count;
+            best = region;
+        }
+    }
+    for (x, y) in grid.floor_cells() {
+        if !best[(y * grid.w + x) as usize] {
+            grid.set(x, y, true);
+        }
+    }
+}
+
+// Puts fountains against walls, each blocking the floor cell its basin sits on. A fountain is
+// only kept if the floor stays connected with it in place.
+fn place_fountains(
+    grid: &mut Grid,
+    rng: &mut StdRng,
+    count: usize,
+    start: (i32, i32),
+) -> Vec<(i32, i32)> {
+    let mut candidates: Vec<(i32, i32)> = grid
+        .floor_cells()
+        .into_iter()
+        .filter(|&(x, y)| grid.solid(x, y - 1) && grid.contains(x, y - 1) && (x, y) != start)
+        .collect();
+    candidates.shuffle(rng);
+
+    let mut fountains = Vec::new();
+    for (x, y) in candidates {
+        if fountains.len() >= count {
+            break;
+        }
+        grid.set(x, y, true);
+        if grid.connected() {
+            fountains.push((x, y));
+        } else {
+            grid.set(x, y, false);
+        }
+    }
+    fountains
+}
+
+// The smallest dungeon, in cells, that still fits a room with a wall around it.
+pub const MIN_SIZE: i32 = 12;
+
+// Generates a w by h cell dungeon. The same seed, size and style always give the same map.
+pub fn generate(seed: u64, w: i32, h: i32, style: Style) -> Result<Map, String> {
+    if w < MIN_SIZE || h < MIN_SIZE {
+        return Err(format!(
+            "dungeons need to be at least {}x{} cells, not {}x{}",
+            MIN_SIZE, MIN_SIZE, w, h
+        ));
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut grid = Grid::new(w, h, true);
+
+    let start = match style {
+        Style::Rooms => {
+            let rooms = carve_rooms(&mut grid, &mut rng);
+            rooms.first().map(Room::center)
+        }
+        Style::Caves => {
+            carve_caves(&mut grid, &mut rng);
+            grid.floor_cells().choose(&mut rng).copied()
+        }
+    };
+    let start = start.ok_or("the dungeon came out with no floor, try another seed")?;
+
+    let fountain_count = grid.floor_cells().len() / 150 + 1;
+    let fountains = place_fountains(&mut grid, &mut rng, fountain_count, start);
+
+    let mut map = Map {
+        tiles: Vec::new(),
+        animations: Vec::new(),
+        forbidden: Vec::new(),
+        triggers: Vec::new(),
+        doors: Vec::new(),
+        entries: vec![MapEntry {
+            name: "start".to_string(),
+            x: start.0 * CELL + CELL / 2,
+            y: start.1 * CELL + CELL / 2,
+        }],
+        spawns: Vec::new(),
+        emitters: Vec::new(),
+        items: Vec::new(),
+        layout: Vec::new(),
+        darkness: 210,
+        width: (w * CELL) as u32,
+        height: (h * CELL) as u32,
+    };
+
+    // Fountain basins block movement but stand on floor.
+    let mut art = grid.clone();
+    for (x, y) in &fountains {
+        art.set(*x, *y, false);
+    }
+    map.tiles = autotile::tiles(&art);
+    map.forbidden = autotile::forbidden(&grid);
+
+    for (x, y) in &fountains {
+        let colour = if rng.gen_bool(0.5) { "blue" } else { "red" };
+        map.animations.push(MapAnimation {
+            x: x * CELL,
+            y: (y - 1) * CELL,
+            animation: format!("wall_fountain_mid_{}_anim", colour),
+            frame: 0,
+        });
+        map.animations.push(MapAnimation {
+            x: x * CELL,
+            y: y * CELL,
+            animation: format!("wall_fountain_basin_{}_anim", colour),
+            frame: 0,
+        });
+        map.emitters.push(MapEmitter {
+            x: x * CELL + CELL / 2,
+            y: y * CELL + 4,
+            emitter: "spray".to_string(),
+        });
+    }
+
+    // Monsters go on floor cells well away from where the player starts.
+    let mut spots: Vec<(i32, i32)> = grid
+        .floor_cells()
+        .into_iter()
+        .filter(|&(x, y)| (x - start.0).abs() + (y - start.1).abs() > 6)
+        .collect();
+    spots.shuffle(&mut rng);
+    let spawn_count = grid.floor_cells().len() / 60 + 1;
+    for (i, (x, y)) in spots.into_iter().take(spawn_count).enumerate() {
+        let (idle, walk, speed) = MONSTERS.choose(&mut rng).unwrap();
+        // Monsters are one cell wide and tall, this puts their feet just below the middle of
+        // the cell.
+        map.spawns.push(MapSpawn {
+            name: format!("monster_{}", i),
+            x: x * CELL,
+            y: y * CELL - 4,
+            idle_anim: idle.to_string(),
+            walk_anim: walk.to_string(),
+            speed: *speed,
+            projectile: None,
+            emitter: None,
+            dialogue: None,
+            script: None,
+        });
+    }
+
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::atlas::parse_tiles;
+
+    // Rebuilds the walkable cells from what ended up in the map, not the generator's grid.
+    fn grid_from_map(map: &Map) -> Grid {
+        let mut grid = Grid::new(map.width as i32 / CELL, map.height as i32 / CELL, false);
+        for f in &map.forbidden {
+            for y in f.y / CELL..(f.y + f.h as i32) / CELL {
+                for x in f.x / CELL..(f.x + f.w as i32) / CELL {
+                    grid.set(x, y, true);
+                }
+            }
+        }
+        grid
+    }
+
+    fn feet_cell(x: i32, y: i32) -> (i32, i32) {
+        (x / CELL, y / CELL)
+    }
+
+    #[test]
+    fn same_seed_same_map() {
+        for style in &[Style::Rooms, Style::Caves] {
+            let a = serde_json::to_string(&generate(7, 40, 30, *style).unwrap()).unwrap();
+            let b = serde_json::to_string(&generate(7, 40, 30, *style).unwrap()).unwrap();
+            let c = serde_json::to_string(&generate(8, 40, 30, *style).unwrap()).unwrap();
+            assert_eq!(a, b);
+            assert_ne!(a, c);
+        }
+    }
+
+    #[test]
+    fn everything_is_reachable_from_start() {
+        for style in &[Style::Rooms, Style::Caves] {
+            for seed in 0..20 {
+                let map = generate(seed, 40, 30, *style).unwrap();
+                let grid = grid_from_map(&map);
+                assert!(grid.connected(), "seed {} {:?} is split up", seed, style);
+
+                let start = &map.entries[0];
+                let (sx, sy) = feet_cell(start.x, start.y);
+                let seen = grid.reachable(sx, sy);
+                assert!(
+                    seen[(sy * grid.w + sx) as usize],
+                    "seed {} start is in a wall",
+                    seed
+                );
+
+                for spawn in &map.spawns {
+                    let (x, y) = feet_cell(spawn.x + CELL / 2, spawn.y + CELL);
+                    assert!(
+                        seen[(y * grid.w + x) as usize],
+                        "seed {} {} can't be reached",
+                        seed,
+                        spawn.name
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn small_sizes_are_refused() {
+        for style in &[Style::Rooms, Style::Caves] {
+            assert!(generate(1, 11, 30, *style).is_err());
+            assert!(generate(1, 40, 0, *style).is_err());
+        }
+        // Caves this small can fill in completely, but that's an error rather than a panic.
+        for seed in 0..50 {
+            assert!(generate(seed, MIN_SIZE, MIN_SIZE, Style::Rooms).is_ok());
+            let _ = generate(seed, MIN_SIZE, MIN_SIZE, Style::Caves);
+        }
+    }
+
+    #[test]
+    fn names_are_in_the_atlas() {
+        let (tiles, anims) = parse_tiles("tiles_list.txt", "generic".to_string()).unwrap();
+        for style in &[Style::Rooms, Style::Caves] {
+            let map = generate(3, 40, 30, *style).unwrap();
+            assert!(map.tiles.iter().all(|t| tiles.contains_key(&t.tile)));
+            assert!(map
+                .animations
+                .iter()
+                .all(|a| anims.contains_key(&a.animation)));
+            assert!(map
+                .spawns
+                .iter()
+                .all(|s| anims.contains_key(&s.idle_anim) && anims.contains_key(&s.walk_anim)));
+        }
+    }
+}
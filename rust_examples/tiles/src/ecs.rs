@@ -0,0 +1,194 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+// Something in the world. The index is reused once it is despawned, with the generation
+// bumped, so an old id kept around somewhere never finds whatever took its place.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}.{}", self.index, self.generation)
+    }
+}
+
+// Hands out entity ids and keeps track of which are alive.
+#[derive(Default)]
+pub struct Entities {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+}
+
+impl Entities {
+    pub fn spawn(&mut self) -> Entity {
+        match self.free.pop() {
+            Some(index) => {
+                let i = index as usize;
+                self.generations[i] += 1;
+                self.alive[i] = true;
+                Entity {
+                    index,
+                    generation: self.generations[i],
+                }
+            }
+            None => {
+                self.generations.push(0);
+                self.alive.push(true);
+                Entity {
+                    index: self.generations.len() as u32 - 1,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    // Returns false if it was already gone.
+    pub fn despawn(&mut self, e: Entity) -> bool {
+        if !self.is_alive(e) {
+            return false;
+        }
+        self.alive[e.index as usize] = false;
+        self.free.push(e.index);
+        true
+    }
+
+    pub fn is_alive(&self, e: Entity) -> bool {
+        let i = e.index as usize;
+        i < self.alive.len() && self.alive[i] && self.generations[i] == e.generation
+    }
+
+    // Every live entity, oldest index first. Systems go through entities in this order so a
+    // step always comes out the same.
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.alive
+            .iter()
+            .enumerate()
+            .filter(|(_, alive)| **alive)
+            .map(move |(i, _)| Entity {
+                index: i as u32,
+                generation: self.generations[i],
+            })
+    }
+
+    pub fn len(&self) -> usize {
+        self.alive.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// One kind of component, stored by entity index. Each slot remembers the generation it was
+// added for, so a stale id gets nothing back.
+pub struct Components<T> {
+    slots: Vec<Option<(u32, T)>>,
+}
+
+impl<T> Default for Components<T> {
+    fn default() -> Components<T> {
+        Components { slots: Vec::new() }
+    }
+}
+
+impl<T> Components<T> {
+    pub fn insert(&mut self, e: Entity, component: T) {
+        let i = e.index as usize;
+        if i >= self.slots.len() {
+            self.slots.resize_with(i + 1, || None);
+        }
+        self.slots[i] = Some((e.generation, component));
+    }
+
+    pub fn remove(&mut self, e: Entity) -> Option<T> {
+        if !self.contains(e) {
+            return None;
+        }
+        self.slots[e.index as usize].take().map(|(_, c)| c)
+    }
+
+    pub fn get(&self, e: Entity) -> Option<&T> {
+        match self.slots.get(e.index as usize) {
+            Some(Some((g, c))) if *g == e.generation => Some(c),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, e: Entity) -> Option<&mut T> {
+        match self.slots.get_mut(e.index as usize) {
+            Some(Some((g, c))) if *g == e.generation => Some(c),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, e: Entity) -> bool {
+        self.get(e).is_some()
+    }
+
+    // In entity index order.
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.slots.iter().enumerate().filter_map(|(i, slot)| {
+            slot.as_ref().map(|(generation, c)| {
+                (
+                    Entity {
+                        index: i as u32,
+                        generation: *generation,
+                    },
+                    c,
+                )
+            })
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.slots.iter_mut().enumerate().filter_map(|(i, slot)| {
+            slot.as_mut().map(|(generation, c)| {
+                (
+                    Entity {
+                        index: i as u32,
+                        generation: *generation,
+                    },
+                    c,
+                )
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn despawned_ids_are_reused_with_a_new_generation() {
+        let mut entities = Entities::default();
+        let a = entities.spawn();
+        let b = entities.spawn();
+        assert!(entities.despawn(a));
+        assert!(!entities.despawn(a));
+        let c = entities.spawn();
+        assert_ne!(a, c);
+        assert!(!entities.is_alive(a));
+        assert!(entities.is_alive(c));
+        assert_eq!(entities.iter().collect::<Vec<_>>(), vec![c, b]);
+    }
+
+    #[test]
+    fn stale_ids_find_nothing() {
+        let mut entities = Entities::default();
+        let mut names: Components<&str> = Components::default();
+        let a = entities.spawn();
+        names.insert(a, "imp");
+        entities.despawn(a);
+        let b = entities.spawn();
+        names.insert(b, "zombie");
+        assert_eq!(names.get(a), None);
+        assert_eq!(names.remove(a), None);
+        assert_eq!(names.get(b), Some(&"zombie"));
+    }
+}
@@ -0,0 +1,124 @@
+// Something that reacts to events, such as the audio or the particles.
+pub trait Subscriber<E> {
+    fn notify(&mut self, event: &E) -> Result<(), String>;
+}
+
+// Events published while a frame is worked out, collected up and then handed to every
+// subscriber in the order they were published. A recording bus also keeps everything it has
+// handed out, for tests and the debug overlay.
+pub struct EventBus<E> {
+    queue: Vec<E>,
+    recorded: Option<Vec<E>>,
+}
+
+impl<E: Clone> EventBus<E> {
+    pub fn new() -> EventBus<E> {
+        EventBus {
+            queue: Vec::new(),
+            recorded: None,
+        }
+    }
+
+    pub fn recording() -> EventBus<E> {
+        EventBus {
+            queue: Vec::new(),
+            recorded: Some(Vec::new()),
+        }
+    }
+
+    pub fn publish(&mut self, event: E) {
+        self.queue.push(event);
+    }
+
+    // Everything published since the last call.
+    pub fn take(&mut self) -> Vec<E> {
+        let events: Vec<E> = self.queue.drain(..).collect();
+        if let Some(recorded) = &mut self.recorded {
+            recorded.extend(events.iter().cloned());
+        }
+        events
+    }
+
+    // Everything taken so far, oldest first. Empty unless the bus is recording.
+    pub fn recorded(&self) -> &[E] {
+        match &self.recorded {
+            Some(recorded) => recorded,
+            None => &[],
+        }
+    }
+}
+
+impl<E: Clone> Default for EventBus<E> {
+    fn default() -> EventBus<E> {
+        EventBus::new()
+    }
+}
+
+// Hands each event to every subscriber. A subscriber failing, say a sound that won't load, is
+// logged and everyone else still hears the event; it shouldn't end the game.
+pub fn dispatch<E>(events: &[E], subscribers: &mut [&mut dyn Subscriber<E>]) {
+    for event in events {
+        for subscriber in subscribers.iter_mut() {
+            if let Err(e) = subscriber.notify(event) {
+                eprintln!("{}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Counter(Vec<u32>);
+
+    impl Subscriber<u32> for Counter {
+        fn notify(&mut self, event: &u32) -> Result<(), String> {
+            self.0.push(*event);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn subscribers_hear_events_in_order() {
+        let mut bus = EventBus::new();
+        bus.publish(1);
+        bus.publish(2);
+        let (mut a, mut b) = (Counter::default(), Counter::default());
+        dispatch(&bus.take(), &mut [&mut a, &mut b]);
+        assert_eq!(a.0, vec![1, 2]);
+        assert_eq!(b.0, vec![1, 2]);
+        assert!(bus.take().is_empty());
+    }
+
+    struct Broken;
+
+    impl Subscriber<u32> for Broken {
+        fn notify(&mut self, event: &u32) -> Result<(), String> {
+            Err(format!("can't handle {}", event))
+        }
+    }
+
+    #[test]
+    fn a_failing_subscriber_doesnt_stop_the_rest() {
+        let mut counter = Counter::default();
+        dispatch(&[1, 2], &mut [&mut Broken, &mut counter]);
+        assert_eq!(counter.0, vec![1, 2]);
+    }
+
+    #[test]
+    fn only_recording_buses_keep_history() {
+        let mut bus = EventBus::recording();
+        bus.publish(1);
+        bus.take();
+        bus.publish(2);
+        bus.take();
+        assert_eq!(bus.recorded(), &[1, 2]);
+
+        let mut bus = EventBus::new();
+        bus.publish(1);
+        bus.take();
+        assert!(bus.recorded().is_empty());
+    }
+}
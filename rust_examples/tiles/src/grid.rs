@@ -0,0 +1,141 @@
+use std::collections::VecDeque;
+
+// A map cut into 16x16 cells, each either solid (a wall) or open floor. Anything outside the
+// grid counts as solid.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Grid {
+    pub w: i32,
+    pub h: i32,
+    solid: Vec<bool>,
+}
+
+pub const CELL: i32 = 16;
+
+impl Grid {
+    pub fn new(w: i32, h: i32, solid: bool) -> Grid {
+        Grid {
+            w,
+            h,
+            solid: vec![solid; (w * h) as usize],
+        }
+    }
+
+    // One string per row, '.' is floor and anything else is solid.
+    pub fn from_layout(rows: &[String]) -> Grid {
+        let h = rows.len() as i32;
+        let w = rows.iter().map(|r| r.chars().count()).max().unwrap_or(0) as i32;
+        let mut grid = Grid::new(w, h, true);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                grid.set(x as i32, y as i32, c != '.');
+            }
+        }
+        grid
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && x < self.w && y < self.h
+    }
+
+    pub fn solid(&self, x: i32, y: i32) -> bool {
+        !self.contains(x, y) || self.solid[(y * self.w + x) as usize]
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, solid: bool) {
+        if self.contains(x, y) {
+            self.solid[(y * self.w + x) as usize] = solid;
+        }
+    }
+
+    pub fn floor_cells(&self) -> Vec<(i32, i32)> {
+        let mut cells = Vec::new();
+        for y in 0..self.h {
+            for x in 0..self.w {
+                if !self.solid(x, y) {
+                    cells.push((x, y));
+                }
+            }
+        }
+        cells
+    }
+
+    // How many of the 8 cells around x, y are solid.
+    pub fn solid_neighbours(&self, x: i32, y: i32) -> usize {
+        let mut count = 0;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if (dx != 0 || dy != 0) && self.solid(x + dx, y + dy) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    // Floor cells reachable from x, y walking in the 4 directions, indexed like the grid.
+    pub fn reachable(&self, x: i32, y: i32) -> Vec<bool> {
+        let mut seen = vec![false; (self.w * self.h) as usize];
+        if self.solid(x, y) {
+            return seen;
+        }
+
+        let mut queue = VecDeque::new();
+        seen[(y * self.w + x) as usize] = true;
+        queue.push_back((x, y));
+        while let Some((x, y)) = queue.pop_front() {
+            for (dx, dy) in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                let (nx, ny) = (x + dx, y + dy);
+                if !self.solid(nx, ny) && !seen[(ny * self.w + nx) as usize] {
+                    seen[(ny * self.w + nx) as usize] = true;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+        seen
+    }
+
+    // Whether every floor cell can be reached from every other.
+    pub fn connected(&self) -> bool {
+        let floor = self.floor_cells();
+        match floor.first() {
+            None => true,
+            Some(&(x, y)) => {
+                let seen = self.reachable(x, y);
+                floor.iter().all(|&(x, y)| seen[(y * self.w + x) as usize])
+            }
+        }
+    }
+
+    // Covers the solid cells with as few rectangles as a greedy sweep finds, as
+    // (x, y, w, h) in cells.
+    pub fn solid_rects(&self) -> Vec<(i32, i32, i32, i32)> {
+        let mut used = vec![false; (self.w * self.h) as usize];
+        let free = |used: &Vec<bool>, x: i32, y: i32| {
+            self.contains(x, y) && self.solid(x, y) && !used[(y * self.w + x) as usize]
+        };
+
+        let mut rects = Vec::new();
+        for y in 0..self.h {
+            for x in 0..self.w {
+                if !free(&used, x, y) {
+                    continue;
+                }
+                let mut w = 1;
+                while free(&used, x + w, y) {
+                    w += 1;
+                }
+                let mut h = 1;
+                while (x..x + w).all(|cx| free(&used, cx, y + h)) {
+                    h += 1;
+                }
+                for cy in y..y + h {
+                    for cx in x..x + w {
+                        used[(cy * self.w + cx) as usize] = true;
+                    }
+                }
+                rects.push((x, y, w, h));
+            }
+        }
+        rects
+    }
+}
@@ -0,0 +1,73 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget};
+
+use tiles::event::Subscriber;
+
+use crate::text::Font;
+use crate::{GameEvent, World, MESSAGE_FRAMES, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// What is drawn over the world during play: the player's stats and the latest message.
+pub struct Hud {
+    // The message and the frames it has left on screen.
+    message: Option<(String, u32)>,
+}
+
+impl Hud {
+    pub fn new() -> Hud {
+        Hud { message: None }
+    }
+
+    pub fn show(&mut self, text: String) {
+        self.message = Some((text, MESSAGE_FRAMES));
+    }
+
+    // Counts the message down. Called once a step, so pausing keeps it up.
+    pub fn tick(&mut self) {
+        if let Some((_, frames_left)) = &mut self.message {
+            *frames_left -= 1;
+            if *frames_left == 0 {
+                self.message = None;
+            }
+        }
+    }
+
+    // Score, health and what the player is carrying in the top left corner, and the message
+    // along the bottom.
+    pub fn draw<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        font: &Font,
+        world: &World,
+    ) -> Result<(), String> {
+        let mut lines = vec![
+            format!("score {}", world.score),
+            format!(
+                "health {}",
+                world.characters.healths.get(world.player).unwrap().0.ceil()
+            ),
+        ];
+        for (item, count) in world.inventory.iter() {
+            lines.push(format!("{} {}", item.replace('_', " "), count));
+        }
+        for (i, line) in lines.iter().enumerate() {
+            font.draw(canvas, line, 20, 20 + i as i32 * 40, 4)?;
+        }
+
+        if let Some((text, _)) = &self.message {
+            canvas.set_draw_color(Color::RGB(0, 0, 0));
+            canvas.fill_rect(Rect::new(0, SCREEN_HEIGHT as i32 - 80, SCREEN_WIDTH, 80))?;
+            font.draw(canvas, text, 20, SCREEN_HEIGHT as i32 - 60, 5)?;
+        }
+        Ok(())
+    }
+}
+
+impl Subscriber<GameEvent> for Hud {
+    fn notify(&mut self, event: &GameEvent) -> Result<(), String> {
+        if let GameEvent::Message(text) = event {
+            self.show(text.clone());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use tiles::map::ItemEffect;
+
+use crate::{GameEvent, World, MAX_HEALTH};
+
+// What the player is carrying, as a count per item name. Kept sorted so the HUD lists it in
+// the same order every frame.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Inventory {
+    stacks: BTreeMap<String, u32>,
+}
+
+impl Inventory {
+    pub fn add(&mut self, item: &str, count: u32) {
+        *self.stacks.entry(item.to_string()).or_insert(0) += count;
+    }
+
+    // Uses up one of the item, if there is one.
+    pub fn take(&mut self, item: &str) -> bool {
+        match self.stacks.get_mut(item) {
+            Some(count) => {
+                *count -= 1;
+                if *count == 0 {
+                    self.stacks.remove(item);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn count(&self, item: &str) -> u32 {
+        self.stacks.get(item).cloned().unwrap_or(0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &u32)> {
+        self.stacks.iter()
+    }
+}
+
+// Items are drawn and animated as characters under this name.
+pub fn item_character(name: &str) -> String {
+    format!("item {}", name)
+}
+
+impl<'a> World<'a> {
+    // Picks up every item the player is close enough to and applies its effects.
+    pub fn update_pickups(&mut self) {
+        let player_pos = self.characters.pos(self.player);
+        let player_health = self.characters.healths.get(self.player).unwrap().0;
+        let characters = &self.characters;
+        let (picked, left): (Vec<_>, Vec<_>) = self.map.items.drain(..).partition(|item| {
+            match characters.named(&item_character(&item.name)) {
+                Some(e) => {
+                    let d = characters.pos(e) - player_pos;
+                    let r = (item.radius * 10) as f32;
+                    d.x * d.x + d.y * d.y < r * r
+                }
+                None => false,
+            }
+        });
+        self.map.items = left;
+
+        let mut health = player_health;
+        for item in picked {
+            for effect in &item.effects {
+                match effect {
+                    ItemEffect::Score { points } => self.score += points,
+                    ItemEffect::Heal { health: amount } => {
+                        health = (health + amount).min(MAX_HEALTH)
+                    }
+                    ItemEffect::Give { item, count } => self.inventory.add(item, *count),
+                }
+            }
+            if let Some(e) = self.characters.named(&item_character(&item.name)) {
+                self.events.publish(GameEvent::ItemPicked {
+                    item: item.name.clone(),
+                    pos: self.characters.pos(e),
+                });
+                self.characters.despawn(e);
+            }
+        }
+        self.characters.healths.get_mut(self.player).unwrap().0 = health;
+    }
+}
@@ -0,0 +1,170 @@
+use std::mem;
+
+use cgmath::Vector2;
+
+use tiles::assets;
+use tiles::map::Map;
+use tiles::terrain::Terrain;
+
+use crate::components::Characters;
+use crate::trigger;
+use crate::{spawn_characters, World};
+
+// Frames to fade out, and again to fade back in, when travelling between levels.
+pub const FADE_FRAMES: u32 = 30;
+
+// A level the player has left, parked exactly as it was so coming back finds it unchanged.
+pub struct Level {
+    pub map: Map,
+    pub characters: Characters,
+}
+
+pub struct Transition {
+    map: String,
+    entry: String,
+    frame: u32,
+}
+
+// Levels are named after their file, "map" is map.json.
+pub fn load_map(name: &str) -> Result<Map, String> {
+    let path = format!("{}.json", name);
+    let data = assets::read_to_string(&path)?;
+    let mut map: Map = serde_json::from_str(&data).map_err(|e| format!("{}: {}", path, e))?;
+    map.apply_layout();
+    Ok(map)
+}
+
+pub fn entry_pos(map: &Map, entry: &str) -> Result<Vector2<f32>, String> {
+    match map.entries.iter().find(|e| e.name == entry) {
+        Some(e) => Ok(Vector2::new((e.x * 10) as f32, (e.y * 10) as f32)),
+        None => Err(format!("map has no entry named {}", entry)),
+    }
+}
+
+// Which of the map's triggers a position is already inside of, so arriving on top of a
+// trigger doesn't fire its enter actions.
+pub fn triggers_inside(map: &Map, pos: Vector2<f32>) -> Vec<bool> {
+    map.triggers
+        .iter()
+        .map(|t| trigger::inside(pos, t.x, t.y, t.w, t.h))
+        .collect()
+}
+
+impl<'a> World<'a> {
+    // Starts fading out towards another level. Ignored if a transition is already running.
+    pub fn travel(&mut self, map: String, entry: String) {
+        if self.transition.is_none() {
+            self.transition = Some(Transition {
+                map,
+                entry,
+                frame: 0,
+            });
+        }
+    }
+
+    // Advances a running transition, switching levels once the screen is black. Returns true
+    // while the world should stay frozen. A level or entry that can't be found is reported
+    // and the player fades back in where they were.
+    pub fn update_transition(&mut self) -> bool {
+        let (map, entry) = match &mut self.transition {
+            None => return false,
+            Some(t) => {
+                t.frame += 1;
+                if t.frame >= FADE_FRAMES * 2 {
+                    self.transition = None;
+                    return true;
+                }
+                if t.frame != FADE_FRAMES {
+                    return true;
+                }
+                (t.map.clone(), t.entry.clone())
+            }
+        };
+
+        if let Err(e) = self.enter_level(&map, &entry) {
+            eprintln!("{}", e);
+        }
+        true
+    }
+
+    // How dark the screen is, 0 when no transition is running and 255 at its midpoint.
+    pub fn fade(&self) -> u8 {
+        match &self.transition {
+            None => 0,
+            Some(t) => {
+                let f = if t.frame <= FADE_FRAMES {
+                    t.frame
+                } else {
+                    FADE_FRAMES * 2 - t.frame
+                };
+                (f * 255 / FADE_FRAMES) as u8
+            }
+        }
+    }
+
+    // Moves the player to an entry of the named level, loading the level the first time it
+    // is visited and parking the current one. Nothing changes if the entry isn't there.
+    pub fn enter_level(&mut self, name: &str, entry: &str) -> Result<(), String> {
+        let pos = if name == self.level {
+            entry_pos(&self.map, entry)?
+        } else {
+            let loaded = if self.levels.contains_key(name) {
+                None
+            } else {
+                let map = load_map(name)?;
+                Some(Level {
+                    characters: spawn_characters(&map, self.anims, self.tuning)?,
+                    map,
+                })
+            };
+            let map = match &loaded {
+                Some(level) => &level.map,
+                None => &self.levels[name].map,
+            };
+            let pos = entry_pos(map, entry).map_err(|e| format!("{}: {}", name, e))?;
+            let mut level = match loaded {
+                Some(level) => level,
+                None => self.levels.remove(name).unwrap(),
+            };
+
+            self.player = self.characters.move_to(self.player, &mut level.characters);
+            let left = Level {
+                map: mem::replace(&mut self.map, level.map),
+                characters: mem::replace(&mut self.characters, level.characters),
+            };
+            self.levels
+                .insert(mem::replace(&mut self.level, name.to_string()), left);
+            pos
+        };
+
+        self.terrain = Terrain::new(&self.map, self.tiles);
+        self.projectiles.clear();
+
+        self.characters.transforms.get_mut(self.player).unwrap().pos = pos;
+        self.inside_triggers = triggers_inside(&self.map, pos);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{load_atlases, World};
+    use tiles::config::{Assets, Gameplay};
+
+    #[test]
+    fn a_missing_entry_leaves_the_player_where_they_are() {
+        let (tiles, anims) = load_atlases(&Assets::default().atlases).unwrap();
+        let tuning = Gameplay::default();
+        let mut world = World::new("map", &tiles, &anims, 0, &tuning).unwrap();
+        let pos = world.characters.pos(world.player);
+
+        let err = world.enter_level("cellar", "nowhere").err().unwrap();
+        assert_eq!(err, "cellar: map has no entry named nowhere");
+        assert_eq!(world.level, "map");
+        assert_eq!(world.characters.pos(world.player), pos);
+        assert!(world.levels.is_empty());
+
+        world.enter_level("cellar", "stairs").unwrap();
+        assert_eq!(world.level, "cellar");
+    }
+}
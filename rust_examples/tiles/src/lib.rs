@@ -0,0 +1,18 @@
+pub mod assets;
+pub mod atlas;
+pub mod autotile;
+pub mod clock;
+pub mod config;
+pub mod dialogue;
+pub mod dungeon;
+pub mod ecs;
+pub mod event;
+pub mod grid;
+pub mod lint;
+pub mod map;
+pub mod scene;
+pub mod script;
+pub mod sheet;
+pub mod snap;
+pub mod terrain;
+pub mod viewport;
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use cgmath::Vector2;
+
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, RenderTarget, Texture, TextureCreator};
+use sdl2::surface::Surface;
+
+use crate::{Camera, Tile, World};
+
+// The player's torch, radius in map pixels and colour.
+pub const TORCH: (u32, [u8; 3]) = (80, [255, 216, 160]);
+
+const LIGHT_SIZE: u32 = 128;
+
+// The darkness is drawn into its own texture, starting from the map's ambient level with every
+// light added on top, and then multiplied over the finished scene. Renderers that can't draw
+// into textures just show the scene unlit.
+pub struct Lighting<'t> {
+    light: Texture<'t>,
+    darkness: Option<Texture<'t>>,
+}
+
+impl<'t> Lighting<'t> {
+    pub fn new<T>(
+        texture_creator: &'t TextureCreator<T>,
+        w: u32,
+        h: u32,
+    ) -> Result<Lighting<'t>, String> {
+        // A round spot, brightest in the middle. Every channel including alpha gets the same
+        // value, so with additive blending it falls off with the square of the distance.
+        let mut surface = Surface::new(LIGHT_SIZE, LIGHT_SIZE, PixelFormatEnum::RGBA8888)?;
+        let pitch = surface.pitch() as usize;
+        surface.with_lock_mut(|pixels| {
+            let half = LIGHT_SIZE as f32 / 2.0;
+            for y in 0..LIGHT_SIZE as usize {
+                for x in 0..LIGHT_SIZE as usize {
+                    let dx = (x as f32 + 0.5 - half) / half;
+                    let dy = (y as f32 + 0.5 - half) / half;
+                    let v = ((1.0 - (dx * dx + dy * dy).sqrt()).max(0.0) * 255.0) as u8;
+                    for c in 0..4 {
+                        pixels[y * pitch + x * 4 + c] = v;
+                    }
+                }
+            }
+        });
+        let mut light = texture_creator
+            .create_texture_from_surface(&surface)
+            .map_err(|e| e.to_string())?;
+        light.set_blend_mode(BlendMode::Add);
+
+        let darkness = match texture_creator.create_texture_target(PixelFormatEnum::RGBA8888, w, h)
+        {
+            Ok(mut darkness) => {
+                darkness.set_blend_mode(BlendMode::Mod);
+                Some(darkness)
+            }
+            Err(_) => None,
+        };
+
+        Ok(Lighting { light, darkness })
+    }
+}
+
+// Every light in the world as (world position, radius in map pixels, colour): lit tiles, lit
+// animation frames such as fountains, and the player's torch.
+fn lights(tiles: &HashMap<String, Tile>, world: &World) -> Vec<(Vector2<f32>, u32, [u8; 3])> {
+    let mut lights = Vec::new();
+
+    for map_tile in &world.map.tiles {
+        let tile = &tiles[&map_tile.tile];
+        if tile.props.light > 0 {
+            lights.push((
+                Vector2::new(
+                    ((map_tile.x * 2 + tile.w as i32) * 5) as f32,
+                    ((map_tile.y * 2 + tile.h as i32) * 5) as f32,
+                ),
+                tile.props.light,
+                tile.props.light_colour,
+            ));
+        }
+    }
+
+    for (e, _) in world.characters.sprites.iter() {
+        let (anim, frame) = world.characters.anim(world.anims, e);
+        let props = &anim.tiles[frame].props;
+        let middle = world.characters.pos(e) - Vector2::new(0.0, (anim.h * 5) as f32);
+        if props.light > 0 {
+            lights.push((middle, props.light, props.light_colour));
+        }
+        if e == world.player {
+            lights.push((middle, TORCH.0, TORCH.1));
+        }
+    }
+
+    lights
+}
+
+// Darkens the scene already on the canvas, leaving light around each light. Does nothing on a
+// map without darkness.
+pub fn draw_lighting<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    lighting: &mut Lighting,
+    tiles: &HashMap<String, Tile>,
+    world: &World,
+    camera: &Camera,
+) -> Result<(), String> {
+    let Lighting { light, darkness } = lighting;
+    let darkness = match darkness {
+        Some(darkness) if world.map.darkness > 0 => darkness,
+        _ => return Ok(()),
+    };
+
+    let ambient = 255 - world.map.darkness;
+    let lights = lights(tiles, world);
+    let mut result = Ok(());
+    let drawn = canvas.with_texture_canvas(darkness, |c| {
+        c.set_draw_color(Color::RGB(ambient, ambient, ambient));
+        c.clear();
+        for (pos, radius, colour) in &lights {
+            let size = radius * 2 * 10;
+            let (x, y) = camera.to_screen(pos.x, pos.y);
+            let dst = Rect::new(x - *radius as i32 * 10, y - *radius as i32 * 10, size, size);
+            if !dst.has_intersection(Rect::new(0, 0, camera.w, camera.h)) {
+                continue;
+            }
+            light.set_color_mod(colour[0], colour[1], colour[2]);
+            if let Err(e) = c.copy(light, None, dst) {
+                result = Err(e);
+            }
+        }
+    });
+    if drawn.is_err() {
+        // No render targets after all, stay unlit.
+        return Ok(());
+    }
+    result?;
+
+    canvas.copy(darkness, None, None)
+}
@@ -0,0 +1,436 @@
+use std::collections::HashMap;
+
+use crate::atlas::{Animation, Tile};
+use crate::grid::{Grid, CELL};
+use crate::map::{Map, TriggerAction};
+use crate::terrain::Terrain;
+
+// Something wrong with a map, at a JSON path into its file such as $.spawns[2].idle_anim.
+#[derive(Debug, PartialEq)]
+pub struct Problem {
+    pub path: String,
+    pub message: String,
+}
+
+fn problem(problems: &mut Vec<Problem>, path: String, message: String) {
+    problems.push(Problem { path, message });
+}
+
+// Whether a point is strictly inside a rectangle, the way trigger::inside treats feet.
+fn inside(px: i32, py: i32, x: i32, y: i32, w: u32, h: u32) -> bool {
+    px > x && px < x + w as i32 && py > y && py < y + h as i32
+}
+
+fn overlap(a: (i32, i32, u32, u32), b: (i32, i32, u32, u32)) -> bool {
+    a.0 < b.0 + b.2 as i32
+        && b.0 < a.0 + a.2 as i32
+        && a.1 < b.1 + b.3 as i32
+        && b.1 < a.1 + a.3 as i32
+}
+
+// Everything the map names has to be in the atlases, or the game panics or fails to load it.
+fn check_names(
+    map: &Map,
+    tiles: &HashMap<String, Tile>,
+    anims: &HashMap<String, Animation>,
+    problems: &mut Vec<Problem>,
+) {
+    let mut tile = |path: String, name: &str| {
+        if !tiles.contains_key(name) {
+            problem(problems, path, format!("no tile named {}", name));
+        }
+    };
+    for (i, t) in map.tiles.iter().enumerate() {
+        tile(format!("$.tiles[{}].tile", i), &t.tile);
+    }
+    for (i, door) in map.doors.iter().enumerate() {
+        tile(format!("$.doors[{}].closed_tile", i), &door.closed_tile);
+        tile(format!("$.doors[{}].open_tile", i), &door.open_tile);
+    }
+
+    let mut anim = |path: String, name: &str| match anims.get(name) {
+        Some(anim) => Some(anim),
+        None => {
+            problem(problems, path, format!("no animation named {}", name));
+            None
+        }
+    };
+    let mut frames = Vec::new();
+    for (i, a) in map.animations.iter().enumerate() {
+        if let Some(found) = anim(format!("$.animations[{}].animation", i), &a.animation) {
+            if a.frame >= found.tiles.len() {
+                frames.push((i, a, found.tiles.len()));
+            }
+        }
+    }
+    for (i, spawn) in map.spawns.iter().enumerate() {
+        anim(format!("$.spawns[{}].idle_anim", i), &spawn.idle_anim);
+        anim(format!("$.spawns[{}].walk_anim", i), &spawn.walk_anim);
+    }
+    for (i, item) in map.items.iter().enumerate() {
+        anim(format!("$.items[{}].anim", i), &item.anim);
+    }
+    for (i, a, count) in frames {
+        problem(
+            problems,
+            format!("$.animations[{}].frame", i),
+            format!("{} only has {} frames", a.animation, count),
+        );
+    }
+
+    for (i, trigger) in map.triggers.iter().enumerate() {
+        for (j, action) in trigger.actions.iter().enumerate() {
+            if let TriggerAction::Door { door } = action {
+                if !map.doors.iter().any(|d| &d.name == door) {
+                    let path = format!("$.triggers[{}].actions[{}].door", i, j);
+                    problem(problems, path, format!("no door named {}", door));
+                }
+            }
+        }
+    }
+}
+
+// Anything placed on the map has to start on it.
+fn check_bounds(map: &Map, problems: &mut Vec<Problem>) {
+    let (w, h) = (map.width as i32, map.height as i32);
+    let mut check = |path: String, x: i32, y: i32| {
+        if x < 0 || y < 0 || x >= w || y >= h {
+            let message = format!("{}, {} is outside the map's {}x{}", x, y, w, h);
+            problem(problems, path, message);
+        }
+    };
+    let positions = map
+        .tiles
+        .iter()
+        .map(|t| ("tiles", t.x, t.y))
+        .chain(map.animations.iter().map(|a| ("animations", a.x, a.y)))
+        .chain(map.forbidden.iter().map(|f| ("forbidden", f.x, f.y)))
+        .chain(map.triggers.iter().map(|t| ("triggers", t.x, t.y)))
+        .chain(map.doors.iter().map(|d| ("doors", d.x, d.y)))
+        .chain(map.entries.iter().map(|e| ("entries", e.x, e.y)))
+        .chain(map.spawns.iter().map(|s| ("spawns", s.x, s.y)))
+        .chain(map.emitters.iter().map(|e| ("emitters", e.x, e.y)))
+        .chain(map.items.iter().map(|i| ("items", i.x, i.y)));
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for (list, x, y) in positions {
+        let i = counts.entry(list).or_insert(0);
+        check(format!("$.{}[{}]", list, i), x, y);
+        *i += 1;
+    }
+
+    // Teleports have to land on the map too.
+    for (i, trigger) in map.triggers.iter().enumerate() {
+        for (j, action) in trigger.actions.iter().enumerate() {
+            if let TriggerAction::Teleport { x, y } = action {
+                check(format!("$.triggers[{}].actions[{}]", i, j), *x, *y);
+            }
+        }
+    }
+}
+
+fn check_overlaps(map: &Map, problems: &mut Vec<Problem>) {
+    let mut seen: HashMap<(i32, i32, &str), usize> = HashMap::new();
+    for (i, t) in map.tiles.iter().enumerate() {
+        if let Some(first) = seen.insert((t.x, t.y, &t.tile), i) {
+            problem(
+                problems,
+                format!("$.tiles[{}]", i),
+                format!(
+                    "{} at {}, {} is already $.tiles[{}]",
+                    t.tile, t.x, t.y, first
+                ),
+            );
+        }
+    }
+
+    for (i, f) in map.forbidden.iter().enumerate() {
+        if f.w == 0 || f.h == 0 {
+            problem(
+                problems,
+                format!("$.forbidden[{}]", i),
+                format!("{}x{} doesn't cover anything", f.w, f.h),
+            );
+            continue;
+        }
+        for (j, g) in map.forbidden.iter().enumerate().take(i) {
+            if g.w > 0 && g.h > 0 && overlap((f.x, f.y, f.w, f.h), (g.x, g.y, g.w, g.h)) {
+                problem(
+                    problems,
+                    format!("$.forbidden[{}]", i),
+                    format!("overlaps $.forbidden[{}]", j),
+                );
+            }
+        }
+    }
+}
+
+// The cells someone can stand in the middle of: on a tile that isn't solid and outside every
+// forbidden area. Doors can be opened, so they don't block. Empty space isn't floor.
+fn floor(map: &Map, tiles: &HashMap<String, Tile>) -> Grid {
+    let w = (map.width as i32 + CELL - 1) / CELL;
+    let h = (map.height as i32 + CELL - 1) / CELL;
+    let mut covered = Grid::new(w, h, true);
+    for t in &map.tiles {
+        if let Some(tile) = tiles.get(&t.tile) {
+            for y in t.y.div_euclid(CELL)..=(t.y + tile.h as i32 - 1).div_euclid(CELL) {
+                for x in t.x.div_euclid(CELL)..=(t.x + tile.w as i32 - 1).div_euclid(CELL) {
+                    covered.set(x, y, false);
+                }
+            }
+        }
+    }
+
+    let terrain = Terrain::new(map, tiles);
+    let mut grid = Grid::new(w, h, true);
+    for (x, y) in covered.floor_cells() {
+        let (px, py) = (x * CELL + CELL / 2, y * CELL + CELL / 2);
+        let blocked = terrain.at(px, py).solid
+            || map
+                .forbidden
+                .iter()
+                .any(|f| inside(px, py, f.x, f.y, f.w, f.h));
+        grid.set(x, y, blocked);
+    }
+    grid
+}
+
+// Characters stand with their feet at the middle of their animation's bottom edge, which
+// mustn't be in a wall or they can never move.
+fn check_spawns(
+    map: &Map,
+    tiles: &HashMap<String, Tile>,
+    anims: &HashMap<String, Animation>,
+    problems: &mut Vec<Problem>,
+) {
+    let terrain = Terrain::new(map, tiles);
+    for (i, spawn) in map.spawns.iter().enumerate() {
+        let anim = match anims.get(&spawn.idle_anim) {
+            Some(anim) => anim,
+            None => continue,
+        };
+        let (fx, fy) = (spawn.x + anim.w as i32 / 2, spawn.y + anim.h as i32);
+        let forbidden = map
+            .forbidden
+            .iter()
+            .position(|f| inside(fx, fy, f.x, f.y, f.w, f.h));
+        let path = format!("$.spawns[{}]", i);
+        if terrain.at(fx, fy).solid {
+            problem(problems, path, format!("{} stands in a wall", spawn.name));
+        } else if let Some(f) = forbidden {
+            let message = format!("{} stands in $.forbidden[{}]", spawn.name, f);
+            problem(problems, path, message);
+        }
+    }
+}
+
+// Floor that can't be walked to from any entry, counting teleports on the way. Each region is
+// reported once, at a tile in it, or the layout row when its tiles came from the layout. The
+// first generated tiles were made from the layout rather than being in the file.
+fn check_reachable(
+    map: &Map,
+    tiles: &HashMap<String, Tile>,
+    generated: usize,
+    problems: &mut Vec<Problem>,
+) {
+    if map.entries.is_empty() {
+        problem(
+            problems,
+            "$.entries".to_string(),
+            "nowhere for the player to arrive".to_string(),
+        );
+        return;
+    }
+    let grid = floor(map, tiles);
+    let index = |x: i32, y: i32| (y * grid.w + x) as usize;
+
+    let mut reached = vec![false; (grid.w * grid.h) as usize];
+    let mut seeds: Vec<(i32, i32)> = map.entries.iter().map(|e| (e.x, e.y)).collect();
+    let mut teleported = vec![false; map.triggers.len()];
+    while let Some((x, y)) = seeds.pop() {
+        let (cx, cy) = (x.div_euclid(CELL), y.div_euclid(CELL));
+        if grid.solid(cx, cy) || reached[index(cx, cy)] {
+            continue;
+        }
+        for (i, seen) in grid.reachable(cx, cy).into_iter().enumerate() {
+            reached[i] |= seen;
+        }
+        for (i, trigger) in map.triggers.iter().enumerate() {
+            let touched = reached.iter().enumerate().any(|(c, &r)| {
+                let (cx, cy) = (c as i32 % grid.w, c as i32 / grid.w);
+                r && overlap(
+                    (trigger.x, trigger.y, trigger.w, trigger.h),
+                    (cx * CELL, cy * CELL, CELL as u32, CELL as u32),
+                )
+            });
+            if touched && !teleported[i] {
+                teleported[i] = true;
+                for action in &trigger.actions {
+                    if let TriggerAction::Teleport { x, y } = action {
+                        seeds.push((*x, *y));
+                    }
+                }
+            }
+        }
+    }
+
+    for (x, y) in grid.floor_cells() {
+        if reached[index(x, y)] {
+            continue;
+        }
+        let region = grid.reachable(x, y);
+        let size = region.iter().filter(|&&r| r).count();
+        for (i, r) in region.into_iter().enumerate() {
+            reached[i] |= r;
+        }
+        let placed = map.tiles[generated..]
+            .iter()
+            .position(|t| t.x.div_euclid(CELL) == x && t.y.div_euclid(CELL) == y);
+        let path = match placed {
+            Some(i) => format!("$.tiles[{}]", i),
+            None => format!("$.layout[{}]", y),
+        };
+        let message = format!(
+            "{} cells of floor from {}, {} can't be reached from any entry",
+            size,
+            x * CELL,
+            y * CELL
+        );
+        problem(problems, path, message);
+    }
+}
+
+// Checks a map as it is in its file. Walls and reachability are checked after the layout has
+// been applied, which keeps the indexes of spawns and hand placed tiles as they were.
+pub fn lint(
+    map: &Map,
+    tiles: &HashMap<String, Tile>,
+    anims: &HashMap<String, Animation>,
+) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    check_names(map, tiles, anims, &mut problems);
+    check_bounds(map, &mut problems);
+    check_overlaps(map, &mut problems);
+
+    let mut built = map.clone();
+    built.apply_layout();
+    let generated = built.tiles.len() - map.tiles.len();
+    check_spawns(&built, tiles, anims, &mut problems);
+    check_reachable(&built, tiles, generated, &mut problems);
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::atlas::parse_tiles;
+    use crate::config::Assets;
+
+    fn load(json: &str) -> Map {
+        serde_json::from_str(json).unwrap()
+    }
+
+    // Both atlases, as the game loads them.
+    fn paths(map: &Map) -> Vec<String> {
+        let (mut tiles, mut anims) = (HashMap::new(), HashMap::new());
+        for atlas in &Assets::default().atlases {
+            let (more_tiles, more_anims) = parse_tiles(&atlas.list, atlas.name.clone()).unwrap();
+            tiles.extend(more_tiles);
+            anims.extend(more_anims);
+        }
+        lint(map, &tiles, &anims)
+            .into_iter()
+            .map(|p| format!("{}: {}", p.path, p.message))
+            .collect()
+    }
+
+    #[test]
+    fn shipped_maps_are_clean() {
+        for name in &["map.json", "cellar.json"] {
+            let map = load(&std::fs::read_to_string(name).unwrap());
+            assert_eq!(paths(&map), Vec::<String>::new(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn reports_names_bounds_and_overlaps() {
+        let map = load(
+            r#"{
+                "width": 64, "height": 64,
+                "tiles": [
+                    {"x": 0, "y": 0, "tile": "floor_1"},
+                    {"x": 0, "y": 0, "tile": "floor_1"},
+                    {"x": 80, "y": 0, "tile": "flor_1"}
+                ],
+                "animations": [{"x": 0, "y": 0, "animation": "coin_anim", "frame": 9}],
+                "forbidden": [
+                    {"x": 0, "y": 0, "w": 32, "h": 32},
+                    {"x": 16, "y": 16, "w": 32, "h": 0},
+                    {"x": 16, "y": 16, "w": 8, "h": 8}
+                ],
+                "entries": [{"name": "start", "x": 8, "y": 8}]
+            }"#,
+        );
+        assert_eq!(
+            paths(&map),
+            vec![
+                "$.tiles[2].tile: no tile named flor_1",
+                "$.animations[0].frame: coin_anim only has 4 frames",
+                "$.tiles[2]: 80, 0 is outside the map's 64x64",
+                "$.tiles[1]: floor_1 at 0, 0 is already $.tiles[0]",
+                "$.forbidden[1]: 32x0 doesn't cover anything",
+                "$.forbidden[2]: overlaps $.forbidden[0]",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unknown_doors_and_teleports_off_the_map() {
+        let map = load(
+            r#"{
+                "width": 64, "height": 64,
+                "tiles": [], "animations": [], "forbidden": [],
+                "entries": [{"name": "start", "x": 8, "y": 8}],
+                "triggers": [{
+                    "x": 0, "y": 0, "w": 16, "h": 16, "when": "interact",
+                    "actions": [
+                        {"action": "door", "door": "gate"},
+                        {"action": "teleport", "x": 100, "y": 8}
+                    ]
+                }]
+            }"#,
+        );
+        assert_eq!(
+            paths(&map),
+            vec![
+                "$.triggers[0].actions[0].door: no door named gate",
+                "$.triggers[0].actions[1]: 100, 8 is outside the map's 64x64",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_walled_in_spawns_and_unreachable_floor() {
+        let map = load(
+            r#"{
+                "width": 80, "height": 16,
+                "layout": ["..#.."],
+                "tiles": [], "animations": [], "forbidden": [],
+                "entries": [{"name": "start", "x": 8, "y": 8}],
+                "spawns": [{
+                    "name": "imp", "x": 24, "y": -8, "speed": 1.0,
+                    "idle_anim": "goblin_idle_anim", "walk_anim": "goblin_run_anim"
+                }]
+            }"#,
+        );
+        assert_eq!(
+            paths(&map),
+            vec![
+                "$.spawns[0]: 24, -8 is outside the map's 80x16",
+                "$.spawns[0]: imp stands in a wall",
+                "$.layout[0]: 2 cells of floor from 48, 0 can't be reached from any entry",
+            ]
+        );
+    }
+}
@@ -3,21 +3,19 @@ extern crate sdl2;
 
 use itertools::Itertools;
 
-use std::cmp::Ordering;
-use std::collections::HashMap;
-use std::fs;
-use std::time::Duration;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::env;
+use std::time::{Duration, Instant};
 
 use rand::prelude::*;
 
 use sdl2::mixer::{AUDIO_S16LSB, DEFAULT_CHANNELS};
 
-use sdl2::event::Event;
-use sdl2::image::{InitFlag, LoadTexture};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::image::InitFlag;
 use sdl2::keyboard::Keycode;
-use sdl2::pixels;
-use sdl2::rect::Rect;
-use sdl2::render::Texture;
+use sdl2::mouse::MouseButton;
 
 use serde::{Deserialize, Serialize};
 
@@ -26,54 +24,69 @@ use cgmath::Vector2;
 //use serde_json::Result;
 //
 
-#[derive(Debug)]
-struct Tile {
-    x: i32,
-    y: i32,
-    w: u32,
-    h: u32,
-    texture: String,
-}
-
-#[derive(Debug)]
-struct Animation {
-    w: u32,
-    h: u32,
-    tiles: Vec<Tile>,
-}
-
-#[derive(Serialize, Deserialize)]
-struct MapTile {
-    x: i32,
-    y: i32,
-    tile: String,
-}
-
-#[derive(Serialize, Deserialize)]
-struct MapAnimation {
-    x: i32,
-    y: i32,
-    animation: String,
-    frame: usize,
-}
-
-#[derive(Serialize, Deserialize)]
-struct MapForbidden {
-    x: i32,
-    y: i32,
-    w: u32,
-    h: u32,
-}
-
-#[derive(Serialize, Deserialize)]
-struct Map {
-    tiles: Vec<MapTile>,
-    animations: Vec<MapAnimation>,
-    forbidden: Vec<MapForbidden>,
-    width: u32,
-    height: u32,
-}
-
+// Everything is drawn to a virtual screen this size, then scaled to fit the window.
+const SCREEN_WIDTH: u32 = 1200;
+const SCREEN_HEIGHT: u32 = 1200;
+
+// The world is stepped once per frame at this rate.
+const FPS: u32 = 60;
+
+// Everyone starts with this much health and healing never goes past it.
+const MAX_HEALTH: f32 = 100.0;
+
+// How long a trigger message stays on screen.
+const MESSAGE_FRAMES: u32 = 180;
+
+mod achievements;
+mod audio;
+mod backend;
+mod behaviour;
+mod components;
+mod controls;
+mod conversation;
+mod debug;
+mod hud;
+mod inventory;
+mod level;
+mod light;
+mod particle;
+mod projectile;
+mod render;
+mod replay;
+mod save;
+mod scenes;
+mod systems;
+mod text;
+mod trigger;
+
+use tiles::assets::{self, Pack};
+use tiles::atlas::{parse_tiles, Animations, Tile};
+use tiles::clock::{self, Clock};
+use tiles::config::{self, Atlas, Config, Gameplay};
+use tiles::ecs::Entity;
+use tiles::event::{self as bus, EventBus};
+use tiles::map::{Map, ProjectileKind};
+use tiles::scene::{Change, SceneStack};
+use tiles::script::Scripts;
+use tiles::snap;
+use tiles::terrain::Terrain;
+use tiles::viewport::Viewport;
+
+use achievements::Achievements;
+use audio::Audio;
+use components::{Ai, Brain, Characters, Emitter, Shooter, Sprite};
+use controls::Keys;
+use conversation::Conversation;
+use hud::Hud;
+use inventory::Inventory;
+use level::{Level, Transition};
+use particle::Particles;
+use projectile::Projectiles;
+use replay::Recording;
+use save::SaveGame;
+use scenes::{MenuItem, Scene, FADE_FRAMES};
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
 enum Dir {
     Left,
     Right,
@@ -88,136 +101,41 @@ impl Dir {
     }
 }
 
-struct Character<'a> {
-    pos: Vector2<f32>,
-    walking: bool,
-    frame: usize,
-    dir: Dir,
-    idle_anim: &'a Animation,
-    walk_anim: &'a Animation,
-    frame_rate: usize,
-    frame_counter: usize,
-    player: bool,
-    speed: f32,
-    w: u32,
-    h: u32,
-    moveable: bool,
-}
-
-impl<'a> Character<'a> {
-    fn new(
-        x: f32,
-        y: f32,
-        idle_anim: &'a Animation,
-        walk_anim: &'a Animation,
-        player: bool,
-        speed: f32,
-    ) -> Character<'a> {
-        let w = idle_anim.w;
-        let h = idle_anim.h;
-
-        Character {
-            pos: Vector2::new(x + (((w * 10) as f32) / 2.0), y + ((h * 10) as f32)),
-            walking: false,
-            frame: 0,
-            dir: Dir::Right,
-            idle_anim: idle_anim,
-            walk_anim: walk_anim,
-            frame_rate: 3,
-            frame_counter: 0,
-            player: player,
-            speed: speed,
-            w: idle_anim.w,
-            h: idle_anim.h,
-            moveable: true,
-        }
-    }
-
-    fn from_anim(x: f32, y: f32, anim: &'a Animation) -> Character<'a> {
-        let w = anim.w;
-        let h = anim.h;
-
-        Character {
-            pos: Vector2::new(
-                x * 10.0 + (((w * 10) as f32) / 2.0),
-                y * 10.0 + ((h * 10) as f32),
-            ),
-            walking: false,
-            frame: 0,
-            dir: Dir::Right,
-            idle_anim: anim,
-            walk_anim: anim,
-            frame_rate: 3,
-            frame_counter: 0,
-            player: false,
-            speed: 0.0,
-            w: anim.w,
-            h: anim.h,
-            moveable: false,
-        }
-    }
-
-    fn update(&mut self, dir: Vector2<f32>) {
-        // Called once per frame, with direction.
-        if dir.x > 0.0 {
-            self.dir = Dir::Right;
-        }
-        if dir.x < 0.0 {
-            self.dir = Dir::Left;
-        }
-
-        // Set if we are moving.
-        let walking_before = self.walking;
-        self.walking = dir.x != 0.0 || dir.y != 0.0;
-
-        if self.walking != walking_before {
-            self.frame = 0;
-        }
-
-        if self.frame_counter >= self.frame_rate {
-            self.frame = (self.frame + 1) % self.anim().tiles.len();
-            self.frame_counter = 0;
-        } else {
-            self.frame_counter += 1;
-        }
-    }
-
-    fn anim(&self) -> &'a Animation {
-        if self.walking {
-            self.walk_anim
-        } else {
-            self.idle_anim
-        }
-    }
-
-    fn width_height(&self) -> (u32, u32) {
-        let anim = self.anim();
-        (anim.w, anim.h)
-    }
-
-    fn top_left(&self) -> (f32, f32) {
-        (
-            self.pos.x - (self.w as f32 / 2.0) * 10.0,
-            self.pos.y - (self.h as f32) * 10.0,
-        )
-    }
-}
-
+#[derive(Clone, Serialize, Deserialize)]
 struct PlayerInput {
     up: bool,
     down: bool,
     left: bool,
     right: bool,
+    #[serde(default)]
+    interact: bool,
+    #[serde(default)]
+    fire: bool,
 }
 
 struct DebugState {
     draw_forbidden: bool,
+    // The overlay with timings, counts, the inspector and the log, see debug.rs.
+    overlay: bool,
+    frame_times: VecDeque<Duration>,
+    last_frame: Instant,
+    // The character shown in the inspector.
+    selected: Option<Entity>,
+    log: VecDeque<String>,
+    // The latest gameplay events and how many times in a row each happened.
+    events: VecDeque<(String, u32)>,
 }
 
 impl DebugState {
     fn new() -> DebugState {
         return DebugState {
             draw_forbidden: false,
+            overlay: false,
+            frame_times: VecDeque::new(),
+            last_frame: Instant::now(),
+            selected: None,
+            log: VecDeque::new(),
+            events: VecDeque::new(),
         };
     }
 }
@@ -229,6 +147,8 @@ impl PlayerInput {
             down: false,
             left: false,
             right: false,
+            interact: false,
+            fire: false,
         };
     }
 
@@ -256,6 +176,9 @@ struct Camera {
     y: f32,
     w: u32,
     h: u32,
+    // The grid in world units the camera and everything drawn snap to. 1 is whole screen
+    // pixels, 10 is whole pixels of the art, for a chunkier but perfectly aligned look.
+    snap: f32,
 }
 
 impl Camera {
@@ -265,55 +188,286 @@ impl Camera {
             h: h,
             x: 0.0,
             y: 0.0,
+            snap: 1.0,
+        }
+    }
+    // Centres on a world position.
+    fn update(&mut self, pos: Vector2<f32>) {
+        self.x = pos.x - (self.w as f32 / 2.0);
+        self.y = pos.y - (self.h as f32 / 2.0);
+    }
+
+    // The screen pixel a world position is drawn at. Everything drawn goes through here so it
+    // is all rounded alike.
+    fn to_screen(&self, x: f32, y: f32) -> (i32, i32) {
+        (
+            snap::to_screen(x, self.x, self.snap),
+            snap::to_screen(y, self.y, self.snap),
+        )
+    }
+
+    // The world position at a screen pixel.
+    fn to_world(&self, x: i32, y: i32) -> (f32, f32) {
+        (
+            x as f32 + snap::snap(self.x, self.snap).round(),
+            y as f32 + snap::snap(self.y, self.snap).round(),
+        )
+    }
+}
+
+// Things that happened during a step. The world publishes them and doesn't care what comes
+// of them; audio, particles, the HUD and achievements each pick out the ones they react to.
+// Positions are in world units.
+#[derive(Clone, Serialize)]
+enum GameEvent {
+    // Published every frame a monster is on top of the player.
+    EnemyReachedPlayer(Entity),
+    ItemPicked {
+        item: String,
+        pos: Vector2<f32>,
+    },
+    CharacterDied {
+        name: Option<String>,
+        pos: Vector2<f32>,
+    },
+    // The index of a trigger on the current map.
+    TriggerEntered(usize),
+    TriggerLeft(usize),
+    ProjectileHit {
+        target: Entity,
+        pos: Vector2<f32>,
+    },
+    Sound(String),
+    Message(String),
+}
+
+// Everything that lives on a map, which is everyone except the player, plus the map's
+// animations and items.
+fn spawn_characters(
+    map: &Map,
+    anims: &Animations,
+    tuning: &Gameplay,
+) -> Result<Characters, String> {
+    let mut characters = Characters::default();
+
+    for spawn in &map.spawns {
+        let sprite = Sprite {
+            idle: anims.id(&spawn.idle_anim)?,
+            walk: anims.id(&spawn.walk_anim)?,
+        };
+        let brain = match (&spawn.dialogue, &spawn.script) {
+            (Some(dialogue), _) => Brain::Talk(dialogue.clone()),
+            (None, Some(script)) => Brain::Script {
+                script: script.clone(),
+                state: rhai::Map::new(),
+            },
+            (None, None) => Brain::Chase,
+        };
+        let e = characters.spawn_character(
+            anims,
+            (spawn.x * 10) as f32,
+            (spawn.y * 10) as f32,
+            sprite,
+            Ai {
+                brain,
+                speed: spawn.speed * tuning.monster_speed,
+            },
+            MAX_HEALTH,
+        );
+        characters.names.insert(e, spawn.name.clone());
+        if let Some(kind) = spawn.projectile {
+            characters.shooters.insert(e, Shooter { kind, reload: 0 });
+        }
+        if let Some(emitter) = &spawn.emitter {
+            characters.emitters.insert(e, Emitter(emitter.clone()));
         }
+        // Arrows go straight through NPCs.
+        if spawn.dialogue.is_some() {
+            characters.colliders.remove(e);
+        }
+    }
+
+    for map_anim in &map.animations {
+        let anim = anims.id(&map_anim.animation)?;
+        characters.spawn_sprite(
+            anims,
+            (map_anim.x * 10) as f32,
+            (map_anim.y * 10) as f32,
+            Sprite {
+                idle: anim,
+                walk: anim,
+            },
+        );
     }
-    fn update(&mut self, character: &Character) -> () {
-        self.x = character.pos.x - (self.w as f32 / 2.0);
-        self.y = character.pos.y - (self.h as f32 / 2.0);
+
+    for item in &map.items {
+        let anim = anims.id(&item.anim)?;
+        let e = characters.spawn_sprite(
+            anims,
+            (item.x * 10) as f32,
+            (item.y * 10) as f32,
+            Sprite {
+                idle: anim,
+                walk: anim,
+            },
+        );
+        characters
+            .names
+            .insert(e, inventory::item_character(&item.name));
+    }
+
+    for (_, animator) in characters.animators.iter_mut() {
+        animator.frame_rate = tuning.animation_frames;
     }
+    Ok(characters)
+}
+
+// How close the player has to be before monsters start shooting, in world units.
+const FIRING_RANGE: f32 = 800.0;
+
+// Everything the simulation touches. Stepping a world only depends on its own state and the
+// input for the frame, so the same inputs and seed always produce the same world.
+struct World<'a> {
+    level: String,
+    map: Map,
+    // Rebuilt whenever map changes.
+    terrain: Terrain,
+    tiles: &'a HashMap<String, Tile>,
+    anims: &'a Animations,
+    characters: Characters,
+    player: Entity,
+    projectiles: Projectiles,
+    levels: HashMap<String, Level>,
+    transition: Option<Transition>,
+    // Nothing in the simulation is random yet. The seed is still recorded and saved so
+    // replays and saves made now keep working once something is.
+    seed: u64,
+    frame: usize,
+    inside_triggers: Vec<bool>,
+    inventory: Inventory,
+    score: u32,
+    // Set and cleared by dialogue, see conversation.rs.
+    flags: BTreeSet<String>,
+    // While talking to someone nothing else moves.
+    conversation: Option<Conversation>,
+    scripts: Scripts,
+    events: EventBus<GameEvent>,
+    tuning: &'a Gameplay,
 }
 
-fn parse_tiles(path: &str, texture: String) -> (HashMap<String, Tile>, HashMap<String, Animation>) {
-    let mut tiles: HashMap<String, Tile> = HashMap::new();
-    let mut anims: HashMap<String, Animation> = HashMap::new();
-
-    for line in fs::read_to_string(path).unwrap().split("\n") {
-        let s: Vec<&str> = line.trim().split(" ").collect();
-        if s.len() == 5 {
-            tiles.insert(
-                String::from(s[0]),
-                Tile {
-                    x: s[1].parse::<i32>().unwrap(),
-                    y: s[2].parse::<i32>().unwrap(),
-                    w: s[3].parse::<u32>().unwrap(),
-                    h: s[4].parse::<u32>().unwrap(),
-                    texture: texture.clone(),
-                },
-            );
-        } else if s.len() == 6 {
-            let count = s[5].parse::<i32>().unwrap();
-            let mut anim = Animation {
-                w: s[3].parse::<u32>().unwrap(),
-                h: s[4].parse::<u32>().unwrap(),
-                tiles: Vec::new(),
-            };
-            let x = s[1].parse::<i32>().unwrap();
-            let y = s[2].parse::<i32>().unwrap();
-
-            for i in 0..count {
-                anim.tiles.push(Tile {
-                    x: x + (anim.w as i32) * i,
-                    y: y,
-                    w: anim.w,
-                    h: anim.h,
-                    texture: texture.clone(),
-                });
+impl<'a> World<'a> {
+    // Starts the player at the "start" entry of the named level.
+    fn new(
+        level: &str,
+        tiles: &'a HashMap<String, Tile>,
+        anims: &'a Animations,
+        seed: u64,
+        tuning: &'a Gameplay,
+    ) -> Result<World<'a>, String> {
+        let map = level::load_map(level)?;
+        let pos = level::entry_pos(&map, "start")?;
+
+        let mut characters = spawn_characters(&map, anims, tuning)?;
+        let player = characters.spawn_character(
+            anims,
+            0.0,
+            0.0,
+            Sprite {
+                idle: anims.id("elf_m_idle_anim")?,
+                walk: anims.id("elf_m_run_anim")?,
+            },
+            Ai {
+                brain: Brain::Player,
+                speed: tuning.player_speed,
+            },
+            MAX_HEALTH,
+        );
+        characters.animators.get_mut(player).unwrap().frame_rate = tuning.animation_frames;
+        characters.transforms.get_mut(player).unwrap().pos = pos;
+        characters.names.insert(player, "player".to_string());
+        characters.shooters.insert(
+            player,
+            Shooter {
+                kind: ProjectileKind::Arrow,
+                reload: 0,
+            },
+        );
+        characters
+            .emitters
+            .insert(player, Emitter("dust".to_string()));
+
+        Ok(World {
+            level: level.to_string(),
+            characters,
+            player,
+            projectiles: Projectiles::new(),
+            levels: HashMap::new(),
+            transition: None,
+            inside_triggers: level::triggers_inside(&map, pos),
+            terrain: Terrain::new(&map, tiles),
+            tiles,
+            anims,
+            map,
+            seed,
+            frame: 0,
+            inventory: Inventory::default(),
+            score: 0,
+            flags: BTreeSet::new(),
+            conversation: None,
+            scripts: Scripts::new(),
+            events: EventBus::new(),
+            tuning,
+        })
+    }
+
+    // Advance one frame. Everything on the level is moved along by the systems in systems.rs,
+    // always in this order. Hands back what was published along the way.
+    fn step(&mut self, pi: &PlayerInput) -> Result<Vec<GameEvent>, String> {
+        if self.update_transition() {
+            return Ok(self.events.take());
+        }
+
+        if self.conversation.is_some() {
+            self.update_conversation(pi);
+            return Ok(self.events.take());
+        }
+
+        self.frame += 1;
+        let update_map_anim = if self.frame == 3 {
+            self.frame = 0;
+            true
+        } else {
+            false
+        };
+
+        for map_anim in &mut self.map.animations {
+            if update_map_anim {
+                let anim = &self.anims[self.anims.id(&map_anim.animation)?];
+                map_anim.frame = (map_anim.frame + 1) % anim.tiles.len();
             }
-            anims.insert(String::from(s[0]), anim);
         }
-    }
 
-    return (tiles, anims);
+        let orders = self.run_scripts();
+        let moves = self.think(pi, &orders);
+        self.animate(&moves);
+        self.shoot(pi, &moves, &orders)?;
+        self.hurt_and_footsteps();
+        self.movement(&moves);
+        self.update_projectiles();
+        self.deaths();
+        self.update_pickups();
+
+        // Talking to someone nearby takes the interact press, so it doesn't also open a door.
+        if pi.interact && self.start_conversation()? {
+            let mut pi = pi.clone();
+            pi.interact = false;
+            self.update_triggers(&pi);
+        } else {
+            self.update_triggers(pi);
+        }
+
+        Ok(self.events.take())
+    }
 }
 
 fn normalize(v: Vector2<f32>) -> Vector2<f32> {
@@ -323,321 +477,569 @@ fn normalize(v: Vector2<f32>) -> Vector2<f32> {
     return v * (1.0 / v.dot(v).sqrt());
 }
 
-fn setup_sound(sdl: &sdl2::Sdl) -> Result<(sdl2::AudioSubsystem, sdl2::mixer::Music), String> {
+// The map pixel a world position is in.
+fn map_point(pos: Vector2<f32>) -> (i32, i32) {
+    ((pos.x / 10.0).floor() as i32, (pos.y / 10.0).floor() as i32)
+}
+
+fn setup_sound(
+    sdl: &sdl2::Sdl,
+    config: &config::Audio,
+) -> Result<(sdl2::AudioSubsystem, sdl2::mixer::Music<'static>), String> {
     let audio = sdl.audio()?;
 
-    let frequency = 44_100;
     let format = AUDIO_S16LSB; // signed 16 bit samples, in little-endian byte order
     let channels = DEFAULT_CHANNELS; // Stereo
-    let chunk_size = 1_024;
 
-    sdl2::mixer::open_audio(frequency, format, channels, chunk_size)?;
+    sdl2::mixer::open_audio(config.frequency, format, channels, config.chunk_size)?;
     let _mixer_context = sdl2::mixer::init(sdl2::mixer::InitFlag::MP3)?;
-    sdl2::mixer::allocate_channels(4);
-
-    let music = sdl2::mixer::Music::from_file("music.mp3")?;
+    sdl2::mixer::allocate_channels(config.channels);
+    sdl2::mixer::Channel::all().set_volume(config.sound_volume);
+
+    // Music is streamed from its bytes as it plays, so they have to last as long as it does.
+    // A packed file's already do; a loose one is read once and kept for good.
+    let bytes = match assets::read(&config.music)? {
+        Cow::Borrowed(bytes) => bytes,
+        Cow::Owned(bytes) => Box::leak(bytes.into_boxed_slice()),
+    };
+    let music = sdl2::mixer::Music::from_static_bytes(bytes)?;
+    sdl2::mixer::Music::set_volume(config.music_volume);
     music.play(-1)?;
 
     Ok((audio, music))
 }
 
-fn main() -> Result<(), String> {
-    let (mut tiles, mut anims) = parse_tiles("tiles_list.txt", "generic".to_string());
-    let (tiles2, anims2) = parse_tiles("slamslime.txt", "slamslime".to_string());
+struct Options {
+    record: Option<String>,
+    replay: Option<String>,
+    seed: Option<u64>,
+    load: Option<String>,
+    camera: Option<(f32, f32)>,
+    render_png: Option<String>,
+    headless: bool,
+    // Where a headless run writes every event published, one JSON object per line.
+    events: Option<String>,
+    config: String,
+    // Settings given on the command line as (dotted key, value), which win over the file.
+    overrides: Vec<(String, String)>,
+}
 
-    tiles.extend(tiles2);
-    anims.extend(anims2);
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        record: None,
+        replay: None,
+        seed: None,
+        load: None,
+        camera: None,
+        render_png: None,
+        headless: false,
+        events: None,
+        config: "config.toml".to_string(),
+        overrides: Vec::new(),
+    };
+    let set = |key: &str, value: String| (key.to_string(), value);
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => options.record = Some(args.next().ok_or("--record needs a file")?),
+            "--replay" => options.replay = Some(args.next().ok_or("--replay needs a file")?),
+            "--seed" => {
+                let seed = args.next().ok_or("--seed needs a number")?;
+                options.seed = Some(seed.parse::<u64>().map_err(|e| e.to_string())?);
+            }
+            "--load" => options.load = Some(args.next().ok_or("--load needs a save slot")?),
+            "--map" => options.overrides.push(set(
+                "gameplay.level",
+                args.next().ok_or("--map needs a level name")?,
+            )),
+            "--camera" => {
+                let camera = args.next().ok_or("--camera needs x,y")?;
+                let xy: Vec<&str> = camera.split(',').collect();
+                if xy.len() != 2 {
+                    return Err(format!("--camera expects x,y, got {}", camera));
+                }
+                let x = xy[0].parse::<f32>().map_err(|e| e.to_string())?;
+                let y = xy[1].parse::<f32>().map_err(|e| e.to_string())?;
+                options.camera = Some((x, y));
+            }
+            "--render-png" => {
+                options.render_png = Some(args.next().ok_or("--render-png needs a file")?)
+            }
+            "--headless" => options.headless = true,
+            "--events" => options.events = Some(args.next().ok_or("--events needs a file")?),
+            "--config" => options.config = args.next().ok_or("--config needs a file")?,
+            "--set" => {
+                let setting = args.next().ok_or("--set needs section.key=value")?;
+                let mut parts = setting.splitn(2, '=');
+                match (parts.next(), parts.next()) {
+                    (Some(key), Some(value)) => options.overrides.push(set(key, value.to_string())),
+                    _ => return Err(format!("--set expects section.key=value, got {}", setting)),
+                }
+            }
+            // Shorthands for common settings.
+            "--renderer" => options.overrides.push(set(
+                "video.renderer",
+                args.next()
+                    .ok_or("--renderer needs software or accelerated")?,
+            )),
+            "--vsync" => options
+                .overrides
+                .push(set("video.vsync", "true".to_string())),
+            "--no-vsync" => options
+                .overrides
+                .push(set("video.vsync", "false".to_string())),
+            "--fullscreen" => options
+                .overrides
+                .push(set("video.fullscreen", "true".to_string())),
+            "--fps" => options
+                .overrides
+                .push(set("video.fps", args.next().ok_or("--fps needs a number")?)),
+            "--pixel-snap" => options.overrides.push(set(
+                "video.pixel_snap",
+                args.next()
+                    .ok_or("--pixel-snap needs a size in world units")?,
+            )),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
 
-    let mut map: Map = serde_json::from_str(&fs::read_to_string("map.json").unwrap()).unwrap();
+    if options.headless && options.replay.is_none() {
+        return Err("--headless needs --replay <file>".to_string());
+    }
+    if options.events.is_some() && !options.headless {
+        return Err("--events needs --headless".to_string());
+    }
+    // A recording starts from a new game on its level, not from wherever a save left off.
+    if options.load.is_some() && (options.record.is_some() || options.replay.is_some()) {
+        return Err("--load can't be used with --record or --replay".to_string());
+    }
 
-    let sdl_context = sdl2::init()?;
-    let video_subsystem = sdl_context.video()?;
-    let _image_context = sdl2::image::init(InitFlag::PNG | InitFlag::JPG)?;
+    Ok(options)
+}
 
-    let (audio, music) = setup_sound(&sdl_context)?;
+fn read_input(events: &sdl2::EventPump, interact: bool, bindings: &Keys) -> PlayerInput {
+    let mut pi = PlayerInput::new();
+    pi.interact = interact;
+    let keys: Vec<Keycode> = events
+        .keyboard_state()
+        .pressed_scancodes()
+        .filter_map(Keycode::from_scancode)
+        .collect();
+
+    if keys.contains(&bindings.up) {
+        pi.up = true;
+    }
+    if keys.contains(&bindings.down) {
+        pi.down = true;
+    }
+    if keys.contains(&bindings.left) {
+        pi.left = true;
+    }
+    if keys.contains(&bindings.right) {
+        pi.right = true;
+    }
+    if keys.contains(&bindings.fire) {
+        pi.fire = true;
+    }
+    pi
+}
 
-    let sound_chunk = sdl2::mixer::Chunk::from_file("coin.wav")?;
-    let window = video_subsystem
-        .window("Tiles", 1200, 1200)
-        .position_centered()
-        .build()
-        .map_err(|e| e.to_string())?;
+// Built with --features embed-assets, the game carries the assets.pack made in the crate's
+// directory by the pack tool, and needs no files alongside it.
+#[cfg(feature = "embed-assets")]
+const EMBEDDED_ASSETS: Option<&[u8]> = Some(include_bytes!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/assets.pack"
+)));
+#[cfg(not(feature = "embed-assets"))]
+const EMBEDDED_ASSETS: Option<&[u8]> = None;
+
+// Mounts the embedded pack, or else the configured one next to the executable if it is
+// there, so the game runs from any directory. Whatever isn't packed comes from assets.dir.
+fn mount_assets(config: &config::Assets) -> Result<(), String> {
+    if let Some(data) = EMBEDDED_ASSETS {
+        return assets::mount(Pack::parse(Cow::Borrowed(data))?);
+    }
+    if config.pack.is_empty() {
+        return Ok(());
+    }
+    let exe = env::current_exe().map_err(|e| e.to_string())?;
+    let path = exe.with_file_name(&config.pack);
+    if path.exists() {
+        assets::mount(Pack::load(&path.to_string_lossy())?)?;
+    }
+    Ok(())
+}
 
-    let mut canvas = window
-        .into_canvas()
-        .software()
-        .build()
-        .map_err(|e| e.to_string())?;
-    let texture_creator = canvas.texture_creator();
+// Where every named character is, one "name x y" line each in name order, as a headless run
+// prints it.
+fn positions(world: &World) -> String {
+    let mut lines = String::new();
+    for (e, name) in world
+        .characters
+        .names
+        .iter()
+        .sorted_by(|(_, n1), (_, n2)| n1.cmp(n2))
+    {
+        let pos = world.characters.pos(e);
+        lines += &format!("{} {} {}\n", name, pos.x, pos.y);
+    }
+    lines
+}
 
-    let mut textures: HashMap<String, Texture> = HashMap::new();
+// Every tile and animation the game draws, from all the atlases.
+fn load_atlases(atlases: &[Atlas]) -> Result<(HashMap<String, Tile>, Animations), String> {
+    let (mut tiles, mut anims) = (HashMap::new(), HashMap::new());
+    for atlas in atlases {
+        let (more_tiles, more_anims) = parse_tiles(&atlas.list, atlas.name.clone())?;
+        tiles.extend(more_tiles);
+        anims.extend(more_anims);
+    }
+    Ok((tiles, Animations::new(anims)))
+}
 
-    textures.insert(
-        "generic".to_string(),
-        texture_creator.load_texture("tiles.png").unwrap(),
-    );
-    textures.insert(
-        "slamslime".to_string(),
-        texture_creator.load_texture("slamslime.png").unwrap(),
-    );
+fn main() -> Result<(), String> {
+    let options = parse_args()?;
+    let config = Config::load(&options.config, &options.overrides)?;
+    mount_assets(&config.assets)?;
+    // Everything else is loaded relative to the asset directory.
+    assets::set_dir(&config.assets.dir).map_err(|e| format!("assets.dir {}", e))?;
+    let keys = Keys::from_config(&config.controls)?;
+
+    let (tiles, anims) = load_atlases(&config.assets.atlases)?;
+
+    let mut recording = match &options.replay {
+        Some(path) => Recording::load(path)?,
+        None => Recording::new(
+            options.seed.unwrap_or_else(|| thread_rng().gen()),
+            config.gameplay.clone(),
+        ),
+    };
 
-    let mut frame = 0;
-    let mut frame_switch = 0;
+    // A replay is played with the settings it was recorded with.
+    let tuning = recording.tuning.clone();
+    let tuning = &tuning;
+    let mut world = World::new(&tuning.level, &tiles, &anims, recording.seed, tuning)?;
 
-    let mut debug_state = DebugState::new();
+    if let Some(slot) = &options.load {
+        SaveGame::load(slot)?.apply(&mut world)?;
+    }
 
-    let mut characters: HashMap<String, Character> = HashMap::new(); //<String, Character>
+    let mut particles = Particles::load(&config.assets.particles, &tiles)?;
 
-    characters.insert(
-        "player".to_string(),
-        Character::new(
-            0.0,
-            100.0,
-            &anims["elf_m_idle_anim"],
-            &anims["elf_m_run_anim"],
-            true,
-            13.0,
-        ),
-    );
-    characters.insert(
-        "imp".to_string(),
-        Character::new(
-            40.0,
-            400.0,
-            &anims["goblin_idle_anim"],
-            &anims["goblin_run_anim"],
-            false,
-            10.0,
-        ),
-    );
-    characters.insert(
-        "slamslime".to_string(),
-        Character::new(
-            100.0,
-            600.0,
-            &anims["slamslime"],
-            &anims["slamslime"],
-            false,
-            3.0,
-        ),
-    );
-    characters.insert(
-        "zombie".to_string(),
-        Character::new(
-            34.0,
-            400.0,
-            &anims["zombie_idle_anim"],
-            &anims["zombie_run_anim"],
-            false,
-            8.0,
-        ),
-    );
+    let mut camera = Camera::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+    camera.snap = config.video.pixel_snap;
+    if let Some((x, y)) = options.camera {
+        camera.x = x;
+        camera.y = y;
+    }
 
-    let mut anim_count = 0;
-    for map_anim in &map.animations {
-        let key = anim_count.to_string();
-        characters.insert(
-            key,
-            Character::from_anim(
-                map_anim.x as f32,
-                map_anim.y as f32,
-                &anims[&map_anim.animation],
-            ),
-        );
-        anim_count += 1;
+    if options.headless || options.render_png.is_some() {
+        // Run the whole recording (if any) without a window, then report where everyone ended
+        // up and/or snapshot the final frame.
+        if options.events.is_some() {
+            world.events = EventBus::recording();
+        }
+        for pi in &recording.frames {
+            world.step(pi)?;
+            for line in world.scripts.take_log() {
+                eprintln!("{}", line);
+            }
+        }
+        if options.headless {
+            print!("{}", positions(&world));
+        }
+        if let Some(path) = &options.events {
+            let mut lines = String::new();
+            for event in world.events.recorded() {
+                lines += &serde_json::to_string(event).map_err(|e| e.to_string())?;
+                lines.push('\n');
+            }
+            std::fs::write(path, lines).map_err(|e| format!("{}: {}", path, e))?;
+        }
+        if let Some(path) = &options.render_png {
+            let _image_context = sdl2::image::init(InitFlag::PNG)?;
+            if options.camera.is_none() {
+                camera.update(world.characters.pos(world.player));
+            }
+            render::render_png(
+                path,
+                &config.assets.atlases,
+                &tiles,
+                &world,
+                &particles,
+                &camera,
+            )?;
+        }
+        return Ok(());
     }
 
-    let mut rng = thread_rng();
+    let sdl_context = sdl2::init()?;
+    let video_subsystem = sdl_context.video()?;
+    let _image_context = sdl2::image::init(InitFlag::PNG | InitFlag::JPG)?;
+
+    let (audio, music) = setup_sound(&sdl_context, &config.audio)?;
+
+    let (mut canvas, render_config) = sdl_window::open(
+        &video_subsystem,
+        "Tiles",
+        backend::render_config(&config.video)?,
+    )?;
+    let texture_creator = canvas.texture_creator();
+
+    let mut viewport = Viewport::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+    let (w, h) = canvas.output_size()?;
+    viewport.resize(w, h);
+
+    let mut renderer = render::Renderer::new(
+        &texture_creator,
+        &config.assets.atlases,
+        SCREEN_WIDTH,
+        SCREEN_HEIGHT,
+    )?;
+    let font = text::Font::load(
+        &texture_creator,
+        &config.assets.font,
+        &config.assets.font_letters,
+    )?;
+
+    let mut sounds = Audio::new(&config.audio);
+    let mut hud = Hud::new();
+    let mut achievements = Achievements::new();
+
+    let mut debug_state = DebugState::new();
+    debug_state.log(format!(
+        "renderer {}{}",
+        canvas.info().name,
+        if render_config.vsync {
+            " with vsync"
+        } else {
+            ""
+        }
+    ));
 
     let mut events = sdl_context.event_pump()?;
 
-    let mut camera = Camera::new(1200, 1200);
+    let mut replay_frame = 0;
+
+    // Up to a quarter of a second is caught up on after a stall.
+    let mut clock = Clock::new(FPS, FPS / 4);
+    let mut last_frame = Instant::now();
+    // Kept until a step takes it, as a frame can go by without one.
+    let mut interact = false;
+
+    // Recordings and replays go straight into the game and can't be restarted, as can loading
+    // a save from the command line.
+    let locked = options.record.is_some() || options.replay.is_some();
+    let mut scenes = SceneStack::new();
+    scenes.apply(Change::Push(if locked || options.load.is_some() {
+        Scene::Gameplay
+    } else {
+        Scene::title()
+    }));
 
     'mainloop: loop {
-        let mut pi = PlayerInput::new();
+        let fading = scenes.fading();
+        let playing = matches!(scenes.top(), Some(Scene::Gameplay)) && !fading;
+        let mut chosen = None;
         for event in events.poll_iter() {
             match event {
                 Event::Quit { .. } => break 'mainloop,
                 Event::KeyDown {
-                    keycode: Some(Keycode::P),
+                    keycode: Some(key), ..
+                } if key == keys.debug => {
+                    debug_state.overlay = !debug_state.overlay;
+                }
+                Event::KeyDown {
+                    keycode: Some(key),
+                    repeat: false,
+                    ..
+                } if key == keys.fullscreen => {
+                    sdl_window::toggle_fullscreen(&mut canvas)?;
+                }
+                // Going fullscreen resizes the window too. The size in pixels can differ from
+                // the window size on high DPI screens, so it is asked for rather than taken
+                // from the event.
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..),
                     ..
                 } => {
+                    let (w, h) = canvas.output_size()?;
+                    viewport.resize(w, h);
+                }
+                // Everything else goes to the top scene, and nowhere while fading.
+                Event::KeyDown {
+                    keycode: Some(key),
+                    repeat: false,
+                    ..
+                } if !playing => {
+                    if let Some(menu) = scenes.top_mut().and_then(Scene::menu_mut) {
+                        if !fading {
+                            chosen = menu.key(key, &keys);
+                        }
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(key),
+                    repeat: false,
+                    ..
+                } if key == keys.pause => scenes.apply(Change::Push(Scene::paused(locked))),
+                Event::KeyDown {
+                    keycode: Some(key),
+                    repeat: false,
+                    ..
+                } if key == keys.interact => {
+                    interact = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(key), ..
+                } if key == keys.show_forbidden => {
                     debug_state.draw_forbidden = !debug_state.draw_forbidden;
                 }
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } if debug_state.overlay && playing => {
+                    if let Some((x, y)) = viewport.to_virtual(x, y) {
+                        debug_state.select_at(&world, &camera, x, y);
+                    }
+                }
+                // Saving and loading would desync a recording, so they are only allowed in
+                // normal play.
+                Event::KeyDown {
+                    keycode: Some(key), ..
+                } if key == keys.quick_save && playing && !locked => {
+                    match SaveGame::from_world(&world).save(save::QUICK_SLOT) {
+                        Ok(_) => debug_state.log("quick saved".to_string()),
+                        Err(e) => debug_state.log(format!("quick save failed: {}", e)),
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(key), ..
+                } if key == keys.quick_load && playing && !locked => {
+                    match SaveGame::load(save::QUICK_SLOT).and_then(|save| save.apply(&mut world)) {
+                        Ok(_) => debug_state.log("quick loaded".to_string()),
+                        Err(e) => debug_state.log(format!("quick load failed: {}", e)),
+                    }
+                }
                 _ => {}
             }
         }
-        let keys: Vec<Keycode> = events
-            .keyboard_state()
-            .pressed_scancodes()
-            .filter_map(Keycode::from_scancode)
-            .collect();
-
-        if keys.contains(&Keycode::W) {
-            pi.up = true;
-        }
-        if keys.contains(&Keycode::S) {
-            pi.down = true;
-        }
-        if keys.contains(&Keycode::A) {
-            pi.left = true;
-        }
-        if keys.contains(&Keycode::D) {
-            pi.right = true;
-        }
 
-        frame += 1;
-        let update_map_anim = if frame == 3 {
-            frame = 0;
-            true
-        } else {
-            false
-        };
-
-        for map_anim in &mut map.animations {
-            if update_map_anim {
-                map_anim.frame = (map_anim.frame + 1) % anims[&map_anim.animation].tiles.len();
+        match chosen {
+            Some(MenuItem::Play) | Some(MenuItem::Restart) => {
+                world = World::new(&tuning.level, &tiles, &anims, thread_rng().gen(), tuning)?;
+                hud = Hud::new();
+                scenes.fade(Change::Reset(Scene::Gameplay), FADE_FRAMES);
             }
+            Some(MenuItem::Resume) => scenes.apply(Change::Pop),
+            Some(MenuItem::Title) => scenes.fade(Change::Reset(Scene::title()), FADE_FRAMES),
+            Some(MenuItem::Quit) => break 'mainloop,
+            None => {}
         }
 
-        // Move our guy.
-        let player_pos = characters["player"].pos;
-
-        for (_, character) in &mut characters {
-            let mov = if character.player {
-                pi.to_dir()
-            } else {
-                let dir = player_pos - character.pos;
-                if dir.dot(dir) < 5.0 {
-                    match sdl2::mixer::Channel::all().play(&sound_chunk, 1) {
-                        Ok(_) => (),
-                        Err(_) => ()
+        // The world steps FPS times a second however often frames are shown, so a frame can
+        // take no steps or several. It only moves while the game is on top, so pausing freezes
+        // it and a replay picks up where it left off.
+        let now = Instant::now();
+        let steps = clock.advance(now - last_frame);
+        last_frame = now;
+        for _ in 0..steps {
+            if matches!(scenes.top(), Some(Scene::Gameplay)) && !scenes.fading() {
+                let pi = if options.replay.is_some() {
+                    match recording.frames.get(replay_frame) {
+                        Some(pi) => pi.clone(),
+                        None => break 'mainloop,
                     }
-                    Vector2::new(0.0, 0.0)
                 } else {
-                    normalize(dir) * 0.5
+                    read_input(&events, interact, &keys)
+                };
+                // A press is only acted on once.
+                interact = false;
+                replay_frame += 1;
+
+                // Edited scripts are picked up once a second, except when that would desync a
+                // recording.
+                if replay_frame % FPS as usize == 0 && !locked {
+                    world.scripts.reload();
                 }
-            } * character.speed;
 
-            character.update(mov);
-
-            // Don't allow movement into restricted areas.
-            let new_pos = character.pos + mov;
+                if options.record.is_some() {
+                    recording.frames.push(pi.clone());
+                }
 
-            let mut allowed = true;
+                let published = world.step(&pi)?;
+                for line in world.scripts.take_log() {
+                    debug_state.log(line);
+                }
+                bus::dispatch(
+                    &published,
+                    &mut [
+                        &mut sounds,
+                        &mut particles,
+                        &mut hud,
+                        &mut achievements,
+                        &mut debug_state,
+                    ],
+                );
+                for name in achievements.take_new() {
+                    hud.show(format!("Achievement: {}", name));
+                }
+                particles.update(&world);
+                hud.tick();
 
-            for forbidden in &map.forbidden {
-                if new_pos.x > (forbidden.x * 10) as f32
-                    && new_pos.x < ((forbidden.x + forbidden.w as i32) * 10) as f32
-                    && new_pos.y > (forbidden.y * 10) as f32
-                    && new_pos.y < ((forbidden.y + forbidden.h as i32) * 10) as f32
-                {
-                    allowed = false;
+                if world.characters.healths.get(world.player).unwrap().0 <= 0.0 {
+                    scenes.fade(Change::Replace(Scene::game_over(locked)), FADE_FRAMES);
                 }
             }
-
-            if allowed {
-                character.pos += mov;
+            scenes.tick();
+            if scenes.is_empty() {
+                break 'mainloop;
             }
         }
 
-        camera.update(&characters["player"]);
-
-        canvas.clear();
-
-        // Draw Map
-        for map_tile in &map.tiles {
-            let tile = &tiles[&map_tile.tile];
-            canvas.copy(
-                &textures[&tile.texture],
-                Rect::new(tile.x, tile.y, tile.w, tile.h),
-                Rect::new(
-                    map_tile.x * 10 - camera.x as i32,
-                    map_tile.y * 10 - camera.y as i32,
-                    tile.w * 10,
-                    tile.h * 10,
-                ),
-            )?;
+        if options.camera.is_none() {
+            camera.update(world.characters.pos(world.player));
         }
 
-        // Draw "player"
-        // We want to sort by y index of their bottom.
-        let characters_in_order = characters
-            .iter()
-            .map(|(k, c)| (k, c.pos.y))
-            .sorted_by(|(_, y1), (_, y2)| y1.partial_cmp(y2).unwrap_or(Ordering::Equal));
-
-        for (k, _) in characters_in_order {
-            let character = &characters[k];
-            let (x, y) = character.top_left();
-            canvas.copy_ex(
-                &textures[&character.anim().tiles[character.frame].texture],
-                Rect::new(
-                    character.anim().tiles[character.frame].x,
-                    character.anim().tiles[character.frame].y,
-                    character.anim().w,
-                    character.anim().h,
-                ),
-                Rect::new(
-                    (x - camera.x) as i32,
-                    (y - camera.y) as i32,
-                    character.anim().w * 10,
-                    character.anim().h * 10,
-                ),
-                0.0,
-                None,
-                character.dir.flip(),
-                false,
-            )?;
-            if debug_state.draw_forbidden {
-                canvas.set_draw_color(pixels::Color::RGB(255, 255, 255));
-                let r = Rect::new(
-                    (x - camera.x) as i32,
-                    (y - camera.y) as i32,
-                    character.anim().w * 10,
-                    character.anim().h * 10,
-                );
-                canvas.draw_rect(r)?;
+        render::fit_to_window(&mut canvas, &viewport)?;
+        for scene in scenes.visible(Scene::opaque) {
+            match scene {
+                Scene::Gameplay => {
+                    render::draw_world(
+                        &mut canvas,
+                        &mut renderer,
+                        &tiles,
+                        &world,
+                        &particles,
+                        &camera,
+                        &debug_state,
+                    )?;
+                    conversation::draw_conversation(&mut canvas, &font, &world)?;
+                    hud.draw(&mut canvas, &font, &world)?;
+                }
+                scene => scene.draw(&mut canvas, &font, &world)?,
             }
         }
-        /*
-        for map_anim in &map.animations {
-            let anim = &anims[&map_anim.animation];
-            let frame_tile = &anim.tiles[map_anim.frame];
-            canvas.copy(
-                &texture,
-                Rect::new(frame_tile.x, frame_tile.y, anim.w, anim.h),
-                Rect::new(map_anim.x * 10, map_anim.y * 10, anim.w * 10, anim.h * 10),
-            )?;
-        }*/
-
-        // Draw debugging
-        if debug_state.draw_forbidden {
-            for forbidden in &map.forbidden {
-                canvas.set_draw_color(pixels::Color::RGB(255, 0, 0));
-                let r = sdl2::rect::Rect::new(
-                    forbidden.x * 10 - camera.x as i32,
-                    forbidden.y * 10 - camera.y as i32,
-                    forbidden.w * 10,
-                    forbidden.h * 10,
-                );
-                canvas.draw_rect(r)?;
-            }
-            for (_, character) in &characters {
-                canvas.set_draw_color(pixels::Color::RGB(255, 255, 0));
-                let r = sdl2::rect::Rect::new(
-                    (character.pos.x - camera.x) as i32,
-                    (character.pos.y - camera.y) as i32,
-                    2,
-                    2,
-                );
-                canvas.draw_rect(r)?;
-            }
+        if debug_state.overlay {
+            debug_state.draw_overlay(&mut canvas, &font, &world, &particles, &camera)?;
         }
+        scenes::draw_fade(&mut canvas, scenes.darkness())?;
 
+        // With vsync presenting waits for the display, otherwise the rest of the frame is
+        // slept off.
         canvas.present();
+        if !render_config.vsync {
+            ::std::thread::sleep(clock::frame_wait(now.elapsed(), render_config.fps));
+        }
+        debug_state.end_frame();
+    }
 
-        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+    if let Some(path) = &options.record {
+        recording.save(path)?;
     }
 
     Ok(())
@@ -0,0 +1,214 @@
+use serde::{Deserialize, Serialize};
+
+use crate::autotile;
+use crate::grid::Grid;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MapTile {
+    pub x: i32,
+    pub y: i32,
+    pub tile: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MapAnimation {
+    pub x: i32,
+    pub y: i32,
+    pub animation: String,
+    pub frame: usize,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MapForbidden {
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Map {
+    pub tiles: Vec<MapTile>,
+    pub animations: Vec<MapAnimation>,
+    pub forbidden: Vec<MapForbidden>,
+    #[serde(default)]
+    pub triggers: Vec<MapTrigger>,
+    #[serde(default)]
+    pub doors: Vec<MapDoor>,
+    #[serde(default)]
+    pub entries: Vec<MapEntry>,
+    #[serde(default)]
+    pub spawns: Vec<MapSpawn>,
+    #[serde(default)]
+    pub emitters: Vec<MapEmitter>,
+    #[serde(default)]
+    pub items: Vec<MapItem>,
+    // Rows of '#' and '.' for walls and floor. When present the wall and floor tiles and their
+    // collision are worked out from it, see apply_layout.
+    #[serde(default)]
+    pub layout: Vec<String>,
+    // How dark the map is away from lights, 0 is fully lit and 255 pitch black.
+    #[serde(default)]
+    pub darkness: u8,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Map {
+    // Turns the layout into autotiled tiles and forbidden rectangles. Hand placed tiles are
+    // kept and drawn on top, so they can decorate the generated walls.
+    pub fn apply_layout(&mut self) {
+        if self.layout.is_empty() {
+            return;
+        }
+        let grid = Grid::from_layout(&self.layout);
+        let mut tiles = autotile::tiles(&grid);
+        tiles.append(&mut self.tiles);
+        self.tiles = tiles;
+        self.forbidden.extend(autotile::forbidden(&grid));
+        self.layout.clear();
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerWhen {
+    Enter,
+    Exit,
+    Interact,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum TriggerAction {
+    // Opens the named door if it is closed, closes it if it is open.
+    Door { door: String },
+    // Moves the player's feet to x, y in map pixels.
+    Teleport { x: i32, y: i32 },
+    // Fades over to a named entry point of another level.
+    Travel { map: String, entry: String },
+    Sound { sound: String },
+    Message { text: String },
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MapTrigger {
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+    pub when: TriggerWhen,
+    pub actions: Vec<TriggerAction>,
+}
+
+// A door blocks its rectangle like a MapForbidden while it is closed, and is drawn with
+// closed_tile or open_tile at its top left corner. A door with a key only opens for a player
+// carrying that item, which uses it up and leaves the door unlocked.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MapDoor {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+    pub closed_tile: String,
+    pub open_tile: String,
+    #[serde(default)]
+    pub open: bool,
+    #[serde(default)]
+    pub key: Option<String>,
+}
+
+impl MapDoor {
+    pub fn tile(&self) -> &str {
+        if self.open {
+            &self.open_tile
+        } else {
+            &self.closed_tile
+        }
+    }
+}
+
+// A named place the player can arrive at. x, y is where their feet end up, in map pixels.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MapEntry {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+}
+
+// A character that lives on this map. x, y is its top left corner in map pixels.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MapSpawn {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub idle_anim: String,
+    pub walk_anim: String,
+    pub speed: f32,
+    // What it shoots at the player, if anything.
+    #[serde(default)]
+    pub projectile: Option<ProjectileKind>,
+    // Particles it kicks up while walking, named in particles.json.
+    #[serde(default)]
+    pub emitter: Option<String>,
+    // Makes it an NPC that stays put and talks, from dialogue/<name>.json.
+    #[serde(default)]
+    pub dialogue: Option<String>,
+    // Moves it by scripts/<name>.rhai instead of chasing the player.
+    #[serde(default)]
+    pub script: Option<String>,
+}
+
+// Something lying on the map that the player picks up by coming within radius map pixels of
+// it. x, y is its top left corner in map pixels. Names must be unique within a map.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MapItem {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub anim: String,
+    #[serde(default = "default_pickup_radius")]
+    pub radius: u32,
+    pub effects: Vec<ItemEffect>,
+}
+
+fn default_pickup_radius() -> u32 {
+    8
+}
+
+fn one() -> u32 {
+    1
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "effect", rename_all = "snake_case")]
+pub enum ItemEffect {
+    Score {
+        points: u32,
+    },
+    Heal {
+        health: f32,
+    },
+    // Goes into the player's inventory, keys included.
+    Give {
+        item: String,
+        #[serde(default = "one")]
+        count: u32,
+    },
+}
+
+// A particle emitter that is always on, named in particles.json. x, y is in map pixels.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MapEmitter {
+    pub x: i32,
+    pub y: i32,
+    pub emitter: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectileKind {
+    Arrow,
+    Glob,
+}
@@ -0,0 +1,263 @@
+use std::collections::{HashMap, HashSet};
+
+use rand::prelude::*;
+
+use serde::Deserialize;
+
+use cgmath::Vector2;
+
+use sdl2::pixels::Color;
+
+use tiles::assets;
+use tiles::ecs::Entity;
+use tiles::event::Subscriber;
+
+use crate::{GameEvent, Tile, World, FPS};
+
+// Emitters stop adding particles while this many are alive, so a busy screen can't bog down
+// the software renderer.
+pub const MAX_PARTICLES: usize = 2000;
+
+// How an emitter's particles behave, loaded by name from particles.json.
+#[derive(Deserialize)]
+pub struct EmitterDef {
+    // Particles a second while the emitter is on.
+    #[serde(default)]
+    pub rate: f32,
+    // Particles let out all at once, for one off effects like hits.
+    #[serde(default)]
+    pub burst: u32,
+    // Frames each particle lives for.
+    pub lifetime: u32,
+    // World units per frame, heading direction degrees (0 is right, 90 is down) give or take
+    // spread degrees.
+    pub speed: f32,
+    pub direction: f32,
+    pub spread: f32,
+    // Added to the downwards speed every frame.
+    #[serde(default)]
+    pub gravity: f32,
+    // Width and height in world units.
+    pub size: u32,
+    // Colour and alpha when a particle is born and when it dies, blended in between.
+    pub start: [u8; 4],
+    pub end: [u8; 4],
+    // An atlas tile to draw instead of a coloured square.
+    #[serde(default)]
+    pub sprite: Option<String>,
+}
+
+// What is running an emitter: one placed on the map, by index, or a character.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Source {
+    Map(usize),
+    Character(Entity),
+}
+
+pub struct Particle {
+    pub pos: Vector2<f32>,
+    vel: Vector2<f32>,
+    age: u32,
+    emitter: usize,
+}
+
+// Purely visual, so it lives outside the world and uses its own rng. Replays and saves never
+// see it.
+pub struct Particles {
+    defs: Vec<EmitterDef>,
+    names: HashMap<String, usize>,
+    particles: Vec<Particle>,
+    // The fraction of a particle each running emitter is still owed from earlier frames.
+    owed: HashMap<Source, f32>,
+    // Emitter names a map or character asked for that don't exist, each reported once.
+    unknown: HashSet<String>,
+    level: String,
+    rng: ThreadRng,
+}
+
+impl Particles {
+    // Sprites are checked against the atlases' tiles here, since drawing looks them up.
+    pub fn load(path: &str, tiles: &HashMap<String, Tile>) -> Result<Particles, String> {
+        let data = assets::read_to_string(path)?;
+        let defs: HashMap<String, EmitterDef> =
+            serde_json::from_str(&data).map_err(|e| format!("{}: {}", path, e))?;
+        for (name, def) in &defs {
+            if let Some(sprite) = &def.sprite {
+                if !tiles.contains_key(sprite) {
+                    return Err(format!("{}: {} has no tile named {}", path, name, sprite));
+                }
+            }
+        }
+
+        let mut particles = Particles {
+            defs: Vec::new(),
+            names: HashMap::new(),
+            particles: Vec::new(),
+            owed: HashMap::new(),
+            unknown: HashSet::new(),
+            level: String::new(),
+            rng: thread_rng(),
+        };
+        for (name, def) in defs {
+            particles.names.insert(name, particles.defs.len());
+            particles.defs.push(def);
+        }
+        Ok(particles)
+    }
+
+    fn emitter(&self, name: &str) -> Result<usize, String> {
+        match self.names.get(name) {
+            Some(i) => Ok(*i),
+            None => Err(format!("no particle emitter named {}", name)),
+        }
+    }
+
+    fn emit(&mut self, emitter: usize, pos: Vector2<f32>, count: u32) {
+        let def = &self.defs[emitter];
+        for _ in 0..count {
+            if self.particles.len() >= MAX_PARTICLES {
+                return;
+            }
+            let angle = (def.direction + self.rng.gen_range(-1.0, 1.0) * def.spread).to_radians();
+            let speed = def.speed * self.rng.gen_range(0.5, 1.0);
+            self.particles.push(Particle {
+                pos,
+                vel: Vector2::new(angle.cos(), angle.sin()) * speed,
+                age: 0,
+                emitter,
+            });
+        }
+    }
+
+    // Lets out the named emitter's whole burst at once.
+    pub fn burst(&mut self, name: &str, pos: Vector2<f32>) -> Result<(), String> {
+        let emitter = self.emitter(name)?;
+        self.emit(emitter, pos, self.defs[emitter].burst);
+        Ok(())
+    }
+
+    // Runs the named emitter for one frame on behalf of source. One that doesn't exist is
+    // reported the first time and skipped after that, rather than stopping the game.
+    fn run(&mut self, source: Source, name: &str, pos: Vector2<f32>) {
+        let emitter = match self.emitter(name) {
+            Ok(emitter) => emitter,
+            Err(e) => {
+                if self.unknown.insert(name.to_string()) {
+                    eprintln!("{}", e);
+                }
+                return;
+            }
+        };
+        let owed = self.owed.entry(source).or_insert(0.0);
+        *owed += self.defs[emitter].rate / FPS as f32;
+        let count = owed.floor();
+        *owed -= count;
+        self.emit(emitter, pos, count as u32);
+    }
+
+    // Runs the map's emitters and those of everyone walking, then moves every particle along.
+    // Changing level clears everything.
+    pub fn update(&mut self, world: &World) {
+        if world.level != self.level {
+            self.particles.clear();
+            self.owed.clear();
+            self.level = world.level.clone();
+        }
+
+        for (i, e) in world.map.emitters.iter().enumerate() {
+            let pos = Vector2::new((e.x * 10) as f32, (e.y * 10) as f32);
+            self.run(Source::Map(i), &e.emitter, pos);
+        }
+        for (e, emitter) in world.characters.emitters.iter() {
+            if world.characters.animators.get(e).unwrap().walking {
+                self.run(Source::Character(e), &emitter.0, world.characters.pos(e));
+            }
+        }
+        // Forget characters that are gone, so their ids don't pile up until the next level.
+        self.owed.retain(|source, _| match source {
+            Source::Map(_) => true,
+            Source::Character(e) => world.characters.emitters.contains(*e),
+        });
+
+        let defs = &self.defs;
+        for p in &mut self.particles {
+            p.vel.y += defs[p.emitter].gravity;
+            p.pos += p.vel;
+            p.age += 1;
+        }
+        self.particles.retain(|p| p.age < defs[p.emitter].lifetime);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Particle> {
+        self.particles.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn def(&self, p: &Particle) -> &EmitterDef {
+        &self.defs[p.emitter]
+    }
+
+    pub fn colour(&self, p: &Particle) -> Color {
+        let def = self.def(p);
+        let t = p.age as f32 / def.lifetime as f32;
+        let mix =
+            |i: usize| (def.start[i] as f32 + (def.end[i] as f32 - def.start[i] as f32) * t) as u8;
+        Color::RGBA(mix(0), mix(1), mix(2), mix(3))
+    }
+}
+
+impl Subscriber<GameEvent> for Particles {
+    fn notify(&mut self, event: &GameEvent) -> Result<(), String> {
+        match event {
+            GameEvent::ProjectileHit { pos, .. } => self.burst("hit", *pos),
+            GameEvent::ItemPicked { pos, .. } => self.burst("pickup", *pos),
+            GameEvent::CharacterDied { pos, .. } => self.burst("death", *pos),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use tiles::config::{Assets, Gameplay};
+    use tiles::map::MapEmitter;
+
+    use crate::load_atlases;
+
+    #[test]
+    fn unknown_sprites_fail_to_load_and_unknown_emitters_are_skipped() {
+        let (tiles, anims) = load_atlases(&Assets::default().atlases).unwrap();
+        let path = env::temp_dir().join(format!("tiles_particles_{}.json", process::id()));
+        let path = path.to_str().unwrap();
+        let def = r#"{"lifetime": 10, "speed": 1, "direction": 0, "spread": 0, "size": 2,
+            "start": [0, 0, 0, 0], "end": [0, 0, 0, 0], "sprite": "coin_anim_f9"}"#;
+        fs::write(path, format!(r#"{{"sparkle": {}}}"#, def)).unwrap();
+        let err = Particles::load(path, &tiles).err().unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(
+            err,
+            format!("{}: sparkle has no tile named coin_anim_f9", path)
+        );
+
+        let tuning = Gameplay::default();
+        let mut world = World::new("map", &tiles, &anims, 0, &tuning).unwrap();
+        world.map.emitters.push(MapEmitter {
+            x: 0,
+            y: 0,
+            emitter: "sparkel".to_string(),
+        });
+        let mut particles = Particles::load(&Assets::default().particles, &tiles).unwrap();
+        particles.update(&world);
+        particles.update(&world);
+        assert!(particles.unknown.contains("sparkel"));
+    }
+}
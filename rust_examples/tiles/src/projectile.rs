@@ -0,0 +1,262 @@
+use std::cmp::Ordering;
+
+use cgmath::{InnerSpace, Vector2};
+
+use tiles::atlas::{AnimId, Animations};
+use tiles::map::ProjectileKind;
+
+use crate::{map_point, trigger, GameEvent, World};
+
+// The most projectiles alive at once. Firing when the pool is full does nothing.
+pub const MAX_PROJECTILES: usize = 512;
+
+// How far above its ground position a projectile is drawn, in world units.
+pub const FLIGHT_HEIGHT: f32 = 80.0;
+
+pub struct Stats {
+    pub anim: &'static str,
+    // World units per frame.
+    pub speed: f32,
+    // Frames before it drops out of the air.
+    pub lifetime: u32,
+    pub damage: f32,
+    // Frames before the same character can fire again.
+    pub reload: u32,
+    // Drawn at this many world units per atlas pixel.
+    pub scale: u32,
+    // Whether the sprite is turned to face where it's going.
+    pub rotate: bool,
+}
+
+pub fn stats(kind: ProjectileKind) -> Stats {
+    match kind {
+        ProjectileKind::Arrow => Stats {
+            anim: "arrow_anim",
+            speed: 40.0,
+            lifetime: 45,
+            damage: 25.0,
+            reload: 20,
+            scale: 10,
+            rotate: true,
+        },
+        ProjectileKind::Glob => Stats {
+            anim: "slime_glob_anim",
+            speed: 15.0,
+            lifetime: 90,
+            damage: 10.0,
+            reload: 90,
+            scale: 4,
+            rotate: false,
+        },
+    }
+}
+
+pub struct Projectile {
+    pub kind: ProjectileKind,
+    // Where it would be on the ground, which is what collides and what it is y-sorted by.
+    pub pos: Vector2<f32>,
+    pub vel: Vector2<f32>,
+    pub anim: AnimId,
+    pub frame: usize,
+    frame_counter: usize,
+    life: u32,
+    // Player projectiles hit monsters, everyone else's hit the player.
+    pub from_player: bool,
+}
+
+impl Projectile {
+    // The angle to draw it at, in degrees clockwise. The art points up.
+    pub fn angle(&self) -> f64 {
+        if stats(self.kind).rotate {
+            f64::from(self.vel.y.atan2(self.vel.x).to_degrees()) + 90.0
+        } else {
+            0.0
+        }
+    }
+}
+
+// A fixed set of slots handed out and given back, so hundreds of short lived projectiles don't
+// mean hundreds of allocations a second.
+pub struct Projectiles {
+    slots: Vec<Option<Projectile>>,
+    free: Vec<usize>,
+}
+
+impl Projectiles {
+    pub fn new() -> Projectiles {
+        Projectiles {
+            slots: (0..MAX_PROJECTILES).map(|_| None).collect(),
+            free: (0..MAX_PROJECTILES).rev().collect(),
+        }
+    }
+
+    pub fn fire(
+        &mut self,
+        anims: &Animations,
+        kind: ProjectileKind,
+        pos: Vector2<f32>,
+        dir: Vector2<f32>,
+        from_player: bool,
+    ) -> Result<(), String> {
+        // Nowhere to go, such as a script aiming at its own feet. Normalizing would give NaN.
+        if dir.magnitude2() == 0.0 {
+            return Ok(());
+        }
+        let slot = match self.free.pop() {
+            Some(slot) => slot,
+            None => return Ok(()),
+        };
+        let stats = stats(kind);
+        self.slots[slot] = Some(Projectile {
+            kind,
+            pos,
+            vel: dir.normalize() * stats.speed,
+            anim: anims.id(stats.anim)?,
+            frame: 0,
+            frame_counter: 0,
+            life: stats.lifetime,
+            from_player,
+        });
+        Ok(())
+    }
+
+    fn remove(&mut self, slot: usize) {
+        if self.slots[slot].take().is_some() {
+            self.free.push(slot);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for slot in 0..self.slots.len() {
+            self.remove(slot);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Projectile> {
+        self.slots.iter().filter_map(|p| p.as_ref())
+    }
+
+    pub fn len(&self) -> usize {
+        MAX_PROJECTILES - self.free.len()
+    }
+}
+
+impl<'a> World<'a> {
+    // Moves every projectile, dropping the ones that ran out of time or hit a wall, a closed
+    // door or a character. Characters that are hit lose health.
+    pub fn update_projectiles(&mut self) {
+        for slot in 0..MAX_PROJECTILES {
+            let hit = match &mut self.projectiles.slots[slot] {
+                None => continue,
+                Some(p) => {
+                    p.pos += p.vel;
+                    p.life = p.life.saturating_sub(1);
+                    if p.frame_counter >= 3 {
+                        p.frame = (p.frame + 1) % self.anims[p.anim].tiles.len();
+                        p.frame_counter = 0;
+                    } else {
+                        p.frame_counter += 1;
+                    }
+
+                    let map = &self.map;
+                    let (x, y) = map_point(p.pos);
+                    let blocked = p.life == 0
+                        || self.terrain.at(x, y).solid
+                        || map
+                            .forbidden
+                            .iter()
+                            .any(|f| trigger::inside(p.pos, f.x, f.y, f.w, f.h))
+                        || map
+                            .doors
+                            .iter()
+                            .any(|d| !d.open && trigger::inside(p.pos, d.x, d.y, d.w, d.h));
+
+                    let pos = p.pos;
+                    let from_player = p.from_player;
+                    let player = self.player;
+                    let characters = &mut self.characters;
+                    // The nearest one is hit, the oldest if two are as near.
+                    let target = characters
+                        .colliders
+                        .iter()
+                        .filter(|(e, c)| {
+                            let d = characters.transforms.get(*e).unwrap().pos - pos;
+                            (*e == player) != from_player
+                                && characters.healths.contains(*e)
+                                && d.x.abs() < c.w / 2.0
+                                && d.y.abs() < c.h / 2.0
+                        })
+                        .map(|(e, _)| ((characters.pos(e) - pos).magnitude2(), e))
+                        .min_by(|(d1, e1), (d2, e2)| {
+                            d1.partial_cmp(d2)
+                                .unwrap_or(Ordering::Equal)
+                                .then(e1.cmp(e2))
+                        })
+                        .map(|(_, e)| e);
+                    match target {
+                        Some(e) => {
+                            let health = characters.healths.get_mut(e).unwrap();
+                            health.0 = (health.0 - stats(p.kind).damage).max(0.0);
+                            self.events.publish(GameEvent::ProjectileHit {
+                                target: e,
+                                pos: pos - Vector2::new(0.0, FLIGHT_HEIGHT),
+                            });
+                            true
+                        }
+                        None => blocked,
+                    }
+                }
+            };
+            if hit {
+                self.projectiles.remove(slot);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load_atlases;
+    use tiles::config::{Assets, Gameplay};
+
+    #[test]
+    fn only_hits_the_other_side() {
+        let (tiles, anims) = load_atlases(&Assets::default().atlases).unwrap();
+        let tuning = Gameplay::default();
+        let mut world = World::new("map", &tiles, &anims, 0, &tuning).unwrap();
+
+        let player = world.player;
+        let slime = world.characters.named("slamslime").unwrap();
+        let health = |world: &World, e| world.characters.healths.get(e).unwrap().0;
+        let (player_health, slime_health) = (health(&world, player), health(&world, slime));
+
+        // Each lands right on both of them, so only from_player decides who is hit.
+        let pos = world.characters.pos(player);
+        world.characters.transforms.get_mut(slime).unwrap().pos = pos;
+        for (kind, from_player) in [(ProjectileKind::Arrow, true), (ProjectileKind::Glob, false)] {
+            let dir = Vector2::new(1.0, 0.0);
+            let start = pos - dir * stats(kind).speed;
+            world
+                .projectiles
+                .fire(&anims, kind, start, dir, from_player)
+                .unwrap();
+            world.update_projectiles();
+        }
+
+        assert_eq!(world.projectiles.len(), 0);
+        assert_eq!(health(&world, slime), slime_health - 25.0);
+        assert_eq!(health(&world, player), player_health - 10.0);
+    }
+
+    #[test]
+    fn firing_nowhere_fires_nothing() {
+        let (_, anims) = load_atlases(&Assets::default().atlases).unwrap();
+        let mut projectiles = Projectiles::new();
+        let pos = Vector2::new(100.0, 100.0);
+        projectiles
+            .fire(&anims, ProjectileKind::Glob, pos, pos - pos, false)
+            .unwrap();
+        assert_eq!(projectiles.len(), 0);
+    }
+}
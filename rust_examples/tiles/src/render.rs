@@ -0,0 +1,402 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use itertools::Itertools;
+
+use sdl2::image::{ImageRWops, SaveSurface};
+use sdl2::pixels;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, RenderTarget, Texture, TextureCreator};
+use sdl2::rwops::RWops;
+use sdl2::surface::Surface;
+
+use crate::light::{self, Lighting};
+use crate::particle::Particles;
+use crate::projectile::{self, Projectile};
+use tiles::assets;
+use tiles::config::Atlas;
+use tiles::ecs::Entity;
+
+use tiles::viewport::Viewport;
+
+use crate::{Camera, DebugState, Tile, World};
+
+enum Sprite<'s> {
+    Character(Entity),
+    Projectile(&'s Projectile),
+}
+
+// Decodes an image from memory, so it can come out of an asset pack, and hands it to f. The
+// surface borrows the bytes it was decoded from, so it can't be returned.
+pub fn with_surface<R, F>(name: &str, f: F) -> Result<R, String>
+where
+    F: FnOnce(&mut Surface) -> Result<R, String>,
+{
+    let bytes = assets::read(name)?;
+    let rwops = RWops::from_bytes(&bytes)?;
+    let mut surface = rwops.load().map_err(|e| format!("{}: {}", name, e))?;
+    f(&mut surface)
+}
+
+fn load_texture<'t, T>(
+    texture_creator: &'t TextureCreator<T>,
+    name: &str,
+) -> Result<Texture<'t>, String> {
+    with_surface(name, |surface| {
+        texture_creator
+            .create_texture_from_surface(&*surface)
+            .map_err(|e| e.to_string())
+    })
+}
+
+pub fn load_textures<'t, T>(
+    texture_creator: &'t TextureCreator<T>,
+    atlases: &[Atlas],
+) -> Result<HashMap<String, Texture<'t>>, String> {
+    let mut textures: HashMap<String, Texture> = HashMap::new();
+
+    for atlas in atlases {
+        textures.insert(
+            atlas.name.clone(),
+            load_texture(texture_creator, &atlas.image)?,
+        );
+    }
+
+    Ok(textures)
+}
+
+// Everything drawing needs that comes from the canvas's texture creator.
+pub struct Renderer<'t> {
+    pub textures: HashMap<String, Texture<'t>>,
+    pub lighting: Lighting<'t>,
+}
+
+impl<'t> Renderer<'t> {
+    // w, h is the size of the canvas it will draw to.
+    pub fn new<T>(
+        texture_creator: &'t TextureCreator<T>,
+        atlases: &[Atlas],
+        w: u32,
+        h: u32,
+    ) -> Result<Renderer<'t>, String> {
+        Ok(Renderer {
+            textures: load_textures(texture_creator, atlases)?,
+            lighting: Lighting::new(texture_creator, w, h)?,
+        })
+    }
+}
+
+// Blacks out the whole window for the bars, then scales and places drawing so the virtual
+// screen lands between them. Canvas clears still cover the whole window.
+pub fn fit_to_window<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    viewport: &Viewport,
+) -> Result<(), String> {
+    canvas.set_scale(1.0, 1.0)?;
+    canvas.set_viewport(None);
+    canvas.set_draw_color(pixels::Color::RGB(0, 0, 0));
+    canvas.clear();
+
+    // The viewport is given in scaled pixels.
+    let scale = viewport.scale();
+    let (x, y, _, _) = viewport.rect();
+    canvas.set_scale(scale, scale)?;
+    canvas.set_viewport(Rect::new(
+        (x as f32 / scale) as i32,
+        (y as f32 / scale) as i32,
+        viewport.w,
+        viewport.h,
+    ));
+    Ok(())
+}
+
+// Draws a whole frame. Works on any canvas, so the window and off-screen surfaces share it.
+pub fn draw_world<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    renderer: &mut Renderer,
+    tiles: &HashMap<String, Tile>,
+    world: &World,
+    particles: &Particles,
+    camera: &Camera,
+    debug_state: &DebugState,
+) -> Result<(), String> {
+    let textures = &renderer.textures;
+    let map = &world.map;
+    let characters = &world.characters;
+    // The screen position of the world's origin.
+    let (ox, oy) = camera.to_screen(0.0, 0.0);
+
+    canvas.set_draw_color(pixels::Color::RGB(0, 0, 0));
+    canvas.clear();
+
+    // Draw Map
+    for map_tile in &map.tiles {
+        let tile = &tiles[&map_tile.tile];
+        canvas.copy(
+            &textures[&tile.texture],
+            Rect::new(tile.x, tile.y, tile.w, tile.h),
+            Rect::new(
+                map_tile.x * 10 + ox,
+                map_tile.y * 10 + oy,
+                tile.w * 10,
+                tile.h * 10,
+            ),
+        )?;
+    }
+
+    for door in &map.doors {
+        let tile = &tiles[door.tile()];
+        canvas.copy(
+            &textures[&tile.texture],
+            Rect::new(tile.x, tile.y, tile.w, tile.h),
+            Rect::new(door.x * 10 + ox, door.y * 10 + oy, tile.w * 10, tile.h * 10),
+        )?;
+    }
+
+    // Draw "player"
+    // Characters and projectiles are drawn together, sorted by the y of their bottom, so they
+    // pass in front of and behind each other.
+    let sprites_in_order = characters
+        .sprites
+        .iter()
+        .map(|(e, _)| (characters.pos(e).y, Sprite::Character(e)))
+        .chain(
+            world
+                .projectiles
+                .iter()
+                .map(|p| (p.pos.y, Sprite::Projectile(p))),
+        )
+        .sorted_by(|(y1, _), (y2, _)| y1.partial_cmp(y2).unwrap_or(Ordering::Equal));
+
+    for (_, sprite) in sprites_in_order {
+        let e = match sprite {
+            Sprite::Character(e) => e,
+            Sprite::Projectile(p) => {
+                let scale = projectile::stats(p.kind).scale;
+                let anim = &world.anims[p.anim];
+                let tile = &anim.tiles[p.frame];
+                let (w, h) = (anim.w * scale, anim.h * scale);
+                let (x, y) = camera.to_screen(p.pos.x, p.pos.y - projectile::FLIGHT_HEIGHT);
+                canvas.copy_ex(
+                    &textures[&tile.texture],
+                    Rect::new(tile.x, tile.y, tile.w, tile.h),
+                    Rect::new(x - w as i32 / 2, y - h as i32 / 2, w, h),
+                    p.angle(),
+                    None,
+                    false,
+                    false,
+                )?;
+                continue;
+            }
+        };
+        let (anim, frame) = characters.anim(world.anims, e);
+        let tile = &anim.tiles[frame];
+        let (x, y, w, h) = characters.bounds(world.anims, e);
+        let (x, y) = camera.to_screen(x, y);
+        let dst = Rect::new(x, y, w, h);
+        canvas.copy_ex(
+            &textures[&tile.texture],
+            Rect::new(tile.x, tile.y, anim.w, anim.h),
+            dst,
+            0.0,
+            None,
+            characters.transforms.get(e).unwrap().dir.flip(),
+            false,
+        )?;
+        if debug_state.draw_forbidden {
+            canvas.set_draw_color(pixels::Color::RGB(255, 255, 255));
+            canvas.draw_rect(dst)?;
+        }
+    }
+    /*
+    for map_anim in &map.animations {
+        let anim = &anims[&map_anim.animation];
+        let frame_tile = &anim.tiles[map_anim.frame];
+        canvas.copy(
+            &texture,
+            Rect::new(frame_tile.x, frame_tile.y, anim.w, anim.h),
+            Rect::new(map_anim.x * 10, map_anim.y * 10, anim.w * 10, anim.h * 10),
+        )?;
+    }*/
+
+    // Particles go over everything else so dust and spray aren't hidden behind sprites.
+    canvas.set_blend_mode(BlendMode::Blend);
+    for p in particles.iter() {
+        let def = particles.def(p);
+        let (x, y) = camera.to_screen(p.pos.x, p.pos.y);
+        let dst = Rect::new(
+            x - def.size as i32 / 2,
+            y - def.size as i32 / 2,
+            def.size,
+            def.size,
+        );
+        match &def.sprite {
+            Some(sprite) => {
+                let tile = &tiles[sprite];
+                canvas.copy(
+                    &textures[&tile.texture],
+                    Rect::new(tile.x, tile.y, tile.w, tile.h),
+                    dst,
+                )?;
+            }
+            None => {
+                canvas.set_draw_color(particles.colour(p));
+                canvas.fill_rect(dst)?;
+            }
+        }
+    }
+    canvas.set_blend_mode(BlendMode::None);
+
+    light::draw_lighting(canvas, &mut renderer.lighting, tiles, world, camera)?;
+
+    let fade = world.fade();
+    if fade > 0 {
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(pixels::Color::RGBA(0, 0, 0, fade));
+        canvas.fill_rect(None)?;
+        canvas.set_blend_mode(BlendMode::None);
+    }
+
+    // Draw debugging
+    if debug_state.draw_forbidden {
+        for door in map.doors.iter().filter(|d| !d.open) {
+            canvas.set_draw_color(pixels::Color::RGB(255, 0, 255));
+            canvas.draw_rect(Rect::new(
+                door.x * 10 + ox,
+                door.y * 10 + oy,
+                door.w * 10,
+                door.h * 10,
+            ))?;
+        }
+        for trigger in &map.triggers {
+            canvas.set_draw_color(pixels::Color::RGB(0, 255, 255));
+            canvas.draw_rect(Rect::new(
+                trigger.x * 10 + ox,
+                trigger.y * 10 + oy,
+                trigger.w * 10,
+                trigger.h * 10,
+            ))?;
+        }
+        for forbidden in &map.forbidden {
+            canvas.set_draw_color(pixels::Color::RGB(255, 0, 0));
+            let r = sdl2::rect::Rect::new(
+                forbidden.x * 10 + ox,
+                forbidden.y * 10 + oy,
+                forbidden.w * 10,
+                forbidden.h * 10,
+            );
+            canvas.draw_rect(r)?;
+        }
+        for (_, transform) in characters.transforms.iter() {
+            canvas.set_draw_color(pixels::Color::RGB(255, 255, 0));
+            let (x, y) = camera.to_screen(transform.pos.x, transform.pos.y);
+            let r = sdl2::rect::Rect::new(x, y, 2, 2);
+            canvas.draw_rect(r)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Renders one frame into a software surface, no window or video driver needed, and writes
+// it out as a PNG.
+pub fn render_png(
+    path: &str,
+    atlases: &[Atlas],
+    tiles: &HashMap<String, Tile>,
+    world: &World,
+    particles: &Particles,
+    camera: &Camera,
+) -> Result<(), String> {
+    let surface = Surface::new(camera.w, camera.h, pixels::PixelFormatEnum::RGBA8888)?;
+    let mut canvas = surface.into_canvas()?;
+    let texture_creator = canvas.texture_creator();
+    let mut renderer = Renderer::new(&texture_creator, atlases, camera.w, camera.h)?;
+
+    draw_world(
+        &mut canvas,
+        &mut renderer,
+        tiles,
+        world,
+        particles,
+        camera,
+        &DebugState::new(),
+    )?;
+
+    canvas.into_surface().save(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use sdl2::image::{InitFlag, LoadSurface};
+    use tiles::config::{Assets, Gameplay};
+
+    use crate::{load_atlases, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    const REFERENCE: &str = "tests/fixtures/start.png";
+
+    // Size and RGBA bytes, row by row.
+    fn pixels(path: &str) -> Result<(u32, u32, Vec<u8>), String> {
+        let mut loaded = Surface::from_file(path)?;
+        loaded.set_blend_mode(BlendMode::None)?;
+        let mut surface = Surface::new(
+            loaded.width(),
+            loaded.height(),
+            pixels::PixelFormatEnum::RGBA8888,
+        )?;
+        loaded.blit(None, &mut surface, None)?;
+        let (w, h, pitch) = (surface.width(), surface.height(), surface.pitch());
+        let bytes = surface.without_lock().ok_or("surface needs locking")?;
+        let rows = (0..h as usize).flat_map(|y| {
+            let row = y * pitch as usize;
+            bytes[row..row + w as usize * 4].iter().copied()
+        });
+        Ok((w, h, rows.collect()))
+    }
+
+    // Draws the first frame of a new game and compares it with a reference image. When
+    // drawing is meant to change, check the new image by eye and rerun with TILES_BLESS=1 to
+    // make it the reference. Ignored until the first reference is blessed and committed.
+    #[test]
+    #[ignore = "bless tests/fixtures/start.png first: TILES_BLESS=1 cargo test -- --ignored"]
+    fn first_frame_matches_the_reference() {
+        let assets = Assets::default();
+        let (tiles, anims) = load_atlases(&assets.atlases).unwrap();
+        let tuning = Gameplay::default();
+        let world = World::new(&tuning.level, &tiles, &anims, 0, &tuning).unwrap();
+        let particles = Particles::load(&assets.particles, &tiles).unwrap();
+        let mut camera = Camera::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+        camera.update(world.characters.pos(world.player));
+
+        let _image_context = sdl2::image::init(InitFlag::PNG).unwrap();
+        // Unique to this run, so tests running at the same time don't draw over each other.
+        let out = env::temp_dir().join(format!("tiles_start_{}.png", process::id()));
+        let out = out.to_str().unwrap();
+        render_png(out, &assets.atlases, &tiles, &world, &particles, &camera).unwrap();
+        if env::var_os("TILES_BLESS").is_some() {
+            fs::copy(out, REFERENCE).unwrap();
+        }
+
+        let expected = pixels(REFERENCE)
+            .map_err(|e| format!("{}, rerun with TILES_BLESS=1 to create it", e))
+            .unwrap();
+        let (w, h, got) = pixels(out).unwrap();
+        assert_eq!((w, h), (expected.0, expected.1));
+        let different = got
+            .chunks(4)
+            .zip(expected.2.chunks(4))
+            .filter(|(a, b)| a != b)
+            .count();
+        assert_eq!(
+            different, 0,
+            "{} pixels differ from {}, see {}",
+            different, REFERENCE, out
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+use tiles::config::Gameplay;
+
+use crate::PlayerInput;
+
+// A recorded session: the seed, the gameplay settings, which include the level it started on,
+// and the input for every frame. Playing the frames back with the same seed and settings
+// reproduces the session exactly, whatever the config says at the time.
+#[derive(Serialize, Deserialize)]
+pub struct Recording {
+    pub seed: u64,
+    pub tuning: Gameplay,
+    pub frames: Vec<PlayerInput>,
+}
+
+impl Recording {
+    pub fn new(seed: u64, tuning: Gameplay) -> Recording {
+        Recording {
+            seed,
+            tuning,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Recording, String> {
+        let data = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        serde_json::from_str(&data).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let data = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(path, data).map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{load_atlases, positions, World};
+    use tiles::config::Assets;
+
+    // The fixture's expected positions come from
+    //   tiles --replay tests/fixtures/walk.replay.json --headless
+    // and only need regenerating when gameplay is meant to change.
+    #[test]
+    fn replays_end_where_they_did() {
+        let recording = Recording::load("tests/fixtures/walk.replay.json").unwrap();
+        let (tiles, anims) = load_atlases(&Assets::default().atlases).unwrap();
+        let tuning = &recording.tuning;
+        let mut world = World::new(&tuning.level, &tiles, &anims, recording.seed, tuning).unwrap();
+        for pi in &recording.frames {
+            world.step(pi).unwrap();
+        }
+        let expected = fs::read_to_string("tests/fixtures/walk.positions.txt").unwrap();
+        assert_eq!(positions(&world), expected);
+    }
+}
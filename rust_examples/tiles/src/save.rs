@@ -0,0 +1,201 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+use tiles::atlas::Animations;
+use tiles::ecs::Entity;
+use tiles::map::Map;
+use tiles::terrain::Terrain;
+
+use crate::components::{Animator, Characters, Health, Transform};
+use crate::inventory::Inventory;
+use crate::level::{self, Level};
+use crate::{spawn_characters, World};
+
+// Bump this whenever the layout of SaveGame changes so old saves are rejected instead of
+// loading garbage.
+pub const SAVE_VERSION: u32 = 5;
+
+const SAVE_DIR: &str = "saves";
+
+pub const QUICK_SLOT: &str = "quick";
+
+// The components of a character that change as the game goes on. The rest come back from
+// respawning it off its map.
+#[derive(Serialize, Deserialize)]
+pub struct CharacterState {
+    transform: Transform,
+    animator: Animator,
+    #[serde(default)]
+    health: Option<Health>,
+}
+
+impl CharacterState {
+    fn from_entity(characters: &Characters, e: Entity) -> CharacterState {
+        CharacterState {
+            transform: characters.transforms.get(e).unwrap().clone(),
+            animator: characters.animators.get(e).unwrap().clone(),
+            health: characters.healths.get(e).cloned(),
+        }
+    }
+
+    fn restore(&self, characters: &mut Characters, anims: &Animations, e: Entity) {
+        let mut animator = self.animator.clone();
+        let sprite = characters.sprites.get(e).unwrap();
+        animator.frame %= anims[animator.anim(sprite)].tiles.len();
+        characters.animators.insert(e, animator);
+        characters.transforms.insert(e, self.transform.clone());
+        if let Some(health) = self.health {
+            characters.healths.insert(e, health);
+        }
+    }
+}
+
+// Every level visited so far is saved, not just the current one, so they are all still as the
+// player left them after loading. Only named characters are kept, unnamed scenery just comes
+// back with the map.
+#[derive(Serialize, Deserialize)]
+pub struct LevelState {
+    map: Map,
+    characters: HashMap<String, CharacterState>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SaveGame {
+    version: u32,
+    seed: u64,
+    level: String,
+    player: CharacterState,
+    inventory: Inventory,
+    score: u32,
+    flags: BTreeSet<String>,
+    levels: HashMap<String, LevelState>,
+}
+
+fn slot_path(slot: &str) -> String {
+    format!("{}/{}.json", SAVE_DIR, slot)
+}
+
+fn level_state(map: &Map, characters: &Characters) -> LevelState {
+    LevelState {
+        map: map.clone(),
+        characters: characters
+            .names
+            .iter()
+            .filter(|(_, name)| *name != "player")
+            .map(|(e, name)| (name.clone(), CharacterState::from_entity(characters, e)))
+            .collect(),
+    }
+}
+
+impl SaveGame {
+    pub fn from_world(world: &World) -> SaveGame {
+        let mut levels: HashMap<String, LevelState> = world
+            .levels
+            .iter()
+            .map(|(name, l)| (name.clone(), level_state(&l.map, &l.characters)))
+            .collect();
+        levels.insert(
+            world.level.clone(),
+            level_state(&world.map, &world.characters),
+        );
+
+        SaveGame {
+            version: SAVE_VERSION,
+            seed: world.seed,
+            level: world.level.clone(),
+            player: CharacterState::from_entity(&world.characters, world.player),
+            inventory: world.inventory.clone(),
+            score: world.score,
+            flags: world.flags.clone(),
+            levels,
+        }
+    }
+
+    pub fn load(slot: &str) -> Result<SaveGame, String> {
+        let path = slot_path(slot);
+        let data = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+        let save: SaveGame = serde_json::from_str(&data).map_err(|e| format!("{}: {}", path, e))?;
+        if save.version != SAVE_VERSION {
+            return Err(format!(
+                "{}: save version {} is not supported (expected {})",
+                path, save.version, SAVE_VERSION
+            ));
+        }
+        Ok(save)
+    }
+
+    pub fn save(&self, slot: &str) -> Result<(), String> {
+        let path = slot_path(slot);
+        fs::create_dir_all(SAVE_DIR).map_err(|e| format!("{}: {}", SAVE_DIR, e))?;
+        let data = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(&path, data).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // Characters are respawned from each saved map and then get their saved state back. A save
+    // that doesn't match its maps is rejected before the world is touched.
+    pub fn apply(self, world: &mut World) -> Result<(), String> {
+        let anims = world.anims;
+        let mut levels: HashMap<String, Level> = HashMap::new();
+        for (level_name, state) in self.levels {
+            // Monsters that were killed and items that were picked up aren't in the save and
+            // stay gone.
+            let mut characters = spawn_characters(&state.map, anims, world.tuning)?;
+            let gone: Vec<Entity> = characters
+                .names
+                .iter()
+                .filter(|(_, name)| !state.characters.contains_key(*name))
+                .map(|(e, _)| e)
+                .collect();
+            for e in gone {
+                characters.despawn(e);
+            }
+            for (name, saved) in &state.characters {
+                match characters.named(name) {
+                    Some(e) => saved.restore(&mut characters, anims, e),
+                    None => {
+                        return Err(format!(
+                            "save has unknown character {} in {}",
+                            name, level_name
+                        ))
+                    }
+                }
+            }
+            levels.insert(
+                level_name,
+                Level {
+                    map: state.map,
+                    characters,
+                },
+            );
+        }
+
+        let mut current = match levels.remove(&self.level) {
+            Some(level) => level,
+            None => return Err(format!("save has no state for level {}", self.level)),
+        };
+
+        let player = world
+            .characters
+            .move_to(world.player, &mut current.characters);
+        self.player.restore(&mut current.characters, anims, player);
+
+        world.inside_triggers =
+            level::triggers_inside(&current.map, current.characters.pos(player));
+        world.terrain = Terrain::new(&current.map, world.tiles);
+        world.map = current.map;
+        world.characters = current.characters;
+        world.player = player;
+        world.levels = levels;
+        world.level = self.level;
+        world.inventory = self.inventory;
+        world.score = self.score;
+        world.flags = self.flags;
+        world.conversation = None;
+        world.transition = None;
+        world.projectiles.clear();
+        world.seed = self.seed;
+        Ok(())
+    }
+}
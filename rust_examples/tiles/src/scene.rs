@@ -0,0 +1,163 @@
+// A change to the scene stack.
+pub enum Change<S> {
+    Push(S),
+    Pop,
+    // Swaps the top scene for another.
+    Replace(S),
+    // Throws away every scene and starts over with this one.
+    Reset(S),
+}
+
+// A change waiting for the screen to go dark.
+struct Fade<S> {
+    change: Option<Change<S>>,
+    frame: u32,
+    frames: u32,
+}
+
+// Scenes from the bottom up. Only the top one gets input and moves on, but every scene from
+// the topmost opaque one up is drawn, so an overlay like the pause menu shows the game under it.
+pub struct SceneStack<S> {
+    scenes: Vec<S>,
+    fade: Option<Fade<S>>,
+}
+
+impl<S> SceneStack<S> {
+    pub fn new() -> SceneStack<S> {
+        SceneStack {
+            scenes: Vec::new(),
+            fade: None,
+        }
+    }
+
+    // Makes a change straight away.
+    pub fn apply(&mut self, change: Change<S>) {
+        match change {
+            Change::Push(scene) => self.scenes.push(scene),
+            Change::Pop => {
+                self.scenes.pop();
+            }
+            Change::Replace(scene) => {
+                self.scenes.pop();
+                self.scenes.push(scene);
+            }
+            Change::Reset(scene) => {
+                self.scenes.clear();
+                self.scenes.push(scene);
+            }
+        }
+    }
+
+    // Fades the screen out over frames, makes the change, and fades back in over as many
+    // again. Asking for another fade while one is going is ignored.
+    pub fn fade(&mut self, change: Change<S>, frames: u32) {
+        if self.fade.is_none() {
+            self.fade = Some(Fade {
+                change: Some(change),
+                frame: 0,
+                frames: frames.max(1),
+            });
+        }
+    }
+
+    // Moves a fade along by a frame. Called once a frame.
+    pub fn tick(&mut self) {
+        let fade = match &mut self.fade {
+            Some(fade) => fade,
+            None => return,
+        };
+        fade.frame += 1;
+        let change = if fade.frame >= fade.frames {
+            fade.change.take()
+        } else {
+            None
+        };
+        if fade.frame >= fade.frames * 2 {
+            self.fade = None;
+        }
+        if let Some(change) = change {
+            self.apply(change);
+        }
+    }
+
+    // Input should be ignored while fading, so nothing is chosen twice.
+    pub fn fading(&self) -> bool {
+        self.fade.is_some()
+    }
+
+    // How dark to draw the screen, from 0 for not at all to 1 for black.
+    pub fn darkness(&self) -> f32 {
+        match &self.fade {
+            Some(fade) if fade.frame <= fade.frames => fade.frame as f32 / fade.frames as f32,
+            Some(fade) => (fade.frames * 2 - fade.frame) as f32 / fade.frames as f32,
+            None => 0.0,
+        }
+    }
+
+    pub fn top(&self) -> Option<&S> {
+        self.scenes.last()
+    }
+
+    pub fn top_mut(&mut self) -> Option<&mut S> {
+        self.scenes.last_mut()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scenes.is_empty()
+    }
+
+    // The scenes to draw, bottom first.
+    pub fn visible(&self, opaque: impl Fn(&S) -> bool) -> &[S] {
+        let start = self.scenes.iter().rposition(opaque).unwrap_or(0);
+        &self.scenes[start..]
+    }
+}
+
+impl<S> Default for SceneStack<S> {
+    fn default() -> SceneStack<S> {
+        SceneStack::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_pop_replace_and_reset() {
+        let mut stack = SceneStack::new();
+        stack.apply(Change::Push("game"));
+        stack.apply(Change::Push("pause"));
+        assert_eq!(stack.top(), Some(&"pause"));
+        assert_eq!(stack.visible(|s| *s == "game"), &["game", "pause"]);
+
+        stack.apply(Change::Pop);
+        stack.apply(Change::Replace("over"));
+        stack.apply(Change::Push("pause"));
+        stack.apply(Change::Reset("title"));
+        assert_eq!(stack.visible(|_| false), &["title"]);
+
+        stack.apply(Change::Pop);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn fades_change_scene_when_dark() {
+        let mut stack = SceneStack::new();
+        stack.apply(Change::Push("title"));
+        stack.fade(Change::Replace("game"), 2);
+        stack.fade(Change::Replace("ignored"), 2);
+
+        stack.tick();
+        assert_eq!(stack.top(), Some(&"title"));
+        assert_eq!(stack.darkness(), 0.5);
+        stack.tick();
+        assert_eq!(stack.top(), Some(&"game"));
+        assert_eq!(stack.darkness(), 1.0);
+        stack.tick();
+        stack.tick();
+        assert!(!stack.fading());
+        assert_eq!(stack.darkness(), 0.0);
+        assert_eq!(stack.top(), Some(&"game"));
+    }
+}
@@ -0,0 +1,172 @@
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, RenderTarget};
+
+use crate::controls::Keys;
+use crate::text::Font;
+use crate::{World, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// Frames the screen takes to fade out, and again to fade back in, when changing scene.
+pub const FADE_FRAMES: u32 = 20;
+
+const SCALE: u32 = 5;
+const LINE: i32 = 60;
+const LEFT: i32 = 400;
+
+// Something that can be picked from a menu. What picking it does is up to the main loop, since
+// most of them change the world.
+#[derive(Clone, Copy)]
+pub enum MenuItem {
+    Play,
+    Resume,
+    Restart,
+    Title,
+    Quit,
+}
+
+impl MenuItem {
+    fn text(self) -> &'static str {
+        match self {
+            MenuItem::Play => "play",
+            MenuItem::Resume => "resume",
+            MenuItem::Restart => "restart",
+            MenuItem::Title => "back to title",
+            MenuItem::Quit => "quit",
+        }
+    }
+}
+
+pub struct Menu {
+    items: Vec<MenuItem>,
+    selected: usize,
+}
+
+impl Menu {
+    fn new(items: Vec<MenuItem>) -> Menu {
+        Menu { items, selected: 0 }
+    }
+
+    // Moves the selection with the arrow keys or the up and down controls, and picks it with
+    // return or fire.
+    pub fn key(&mut self, key: Keycode, keys: &Keys) -> Option<MenuItem> {
+        if (key == Keycode::Up || key == keys.up) && self.selected > 0 {
+            self.selected -= 1;
+        } else if (key == Keycode::Down || key == keys.down) && self.selected + 1 < self.items.len()
+        {
+            self.selected += 1;
+        } else if key == Keycode::Return || key == keys.fire {
+            return Some(self.items[self.selected]);
+        }
+        None
+    }
+
+    fn draw<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        font: &Font,
+        top: i32,
+    ) -> Result<(), String> {
+        for (i, item) in self.items.iter().enumerate() {
+            let y = top + LINE * i as i32;
+            if i == self.selected {
+                canvas.set_draw_color(Color::RGB(70, 70, 110));
+                canvas.fill_rect(Rect::new(LEFT - 20, y - 10, 440, LINE as u32))?;
+            }
+            font.draw(canvas, item.text(), LEFT, y, SCALE)?;
+        }
+        Ok(())
+    }
+}
+
+// What is on screen, kept on a tiles::scene::SceneStack. Gameplay is drawn and stepped by the
+// main loop itself; the others are menus.
+pub enum Scene {
+    Title(Menu),
+    Gameplay,
+    // Drawn over the game, which stops moving until it is popped.
+    Paused(Menu),
+    GameOver(Menu),
+}
+
+impl Scene {
+    pub fn title() -> Scene {
+        Scene::Title(Menu::new(vec![MenuItem::Play, MenuItem::Quit]))
+    }
+
+    // Leaving for the title or starting over would break a recording or replay, so locked
+    // sessions can only carry on or quit.
+    pub fn paused(locked: bool) -> Scene {
+        Scene::Paused(Menu::new(if locked {
+            vec![MenuItem::Resume, MenuItem::Quit]
+        } else {
+            vec![MenuItem::Resume, MenuItem::Title, MenuItem::Quit]
+        }))
+    }
+
+    pub fn game_over(locked: bool) -> Scene {
+        Scene::GameOver(Menu::new(if locked {
+            vec![MenuItem::Quit]
+        } else {
+            vec![MenuItem::Restart, MenuItem::Title]
+        }))
+    }
+
+    // Whether it covers the whole screen, so nothing under it needs drawing.
+    pub fn opaque(&self) -> bool {
+        !matches!(self, Scene::Paused(_))
+    }
+
+    pub fn menu_mut(&mut self) -> Option<&mut Menu> {
+        match self {
+            Scene::Title(menu) | Scene::Paused(menu) | Scene::GameOver(menu) => Some(menu),
+            Scene::Gameplay => None,
+        }
+    }
+
+    // Draws a menu scene. Gameplay is left to the main loop.
+    pub fn draw<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        font: &Font,
+        world: &World,
+    ) -> Result<(), String> {
+        match self {
+            Scene::Title(menu) => {
+                canvas.set_draw_color(Color::RGB(0, 0, 0));
+                canvas.fill_rect(Rect::new(0, 0, SCREEN_WIDTH, SCREEN_HEIGHT))?;
+                font.draw(canvas, "tiles", LEFT, 300, SCALE * 3)?;
+                menu.draw(canvas, font, 600)
+            }
+            Scene::Paused(menu) => {
+                canvas.set_blend_mode(BlendMode::Blend);
+                canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+                canvas.fill_rect(Rect::new(0, 0, SCREEN_WIDTH, SCREEN_HEIGHT))?;
+                canvas.set_blend_mode(BlendMode::None);
+                font.draw(canvas, "paused", LEFT, 350, SCALE * 2)?;
+                menu.draw(canvas, font, 550)
+            }
+            Scene::GameOver(menu) => {
+                // Filled rather than cleared, which would colour the bars too.
+                canvas.set_draw_color(Color::RGB(40, 0, 0));
+                canvas.fill_rect(Rect::new(0, 0, SCREEN_WIDTH, SCREEN_HEIGHT))?;
+                font.draw(canvas, "game over", LEFT - 100, 300, SCALE * 2)?;
+                font.draw(canvas, &format!("score {}", world.score), LEFT, 450, SCALE)?;
+                menu.draw(canvas, font, 600)
+            }
+            Scene::Gameplay => Ok(()),
+        }
+    }
+}
+
+// Darkens the whole screen while a scene change fades.
+pub fn draw_fade<T: RenderTarget>(canvas: &mut Canvas<T>, darkness: f32) -> Result<(), String> {
+    if darkness <= 0.0 {
+        return Ok(());
+    }
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, (darkness.min(1.0) * 255.0) as u8));
+    canvas.fill_rect(Rect::new(0, 0, SCREEN_WIDTH, SCREEN_HEIGHT))?;
+    canvas.set_blend_mode(BlendMode::None);
+    Ok(())
+}
@@ -0,0 +1,325 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+
+use crate::assets;
+
+// Limits on what one run of a script may do, so a runaway script stalls a single character
+// for a frame instead of the whole game.
+const MAX_OPERATIONS: u64 = 20_000;
+const MAX_CALL_LEVELS: usize = 16;
+const MAX_EXPR_DEPTH: usize = 32;
+const MAX_COLLECTION: usize = 256;
+const MAX_STRING: usize = 1024;
+
+// What a script asks its character to do this frame. Positions and directions are in world
+// units.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    MoveTowards(f32, f32),
+    MoveDir(f32, f32),
+    Stop,
+    // Switches both the idle and walk animations.
+    PlayAnim(String),
+    PlaySound(String),
+    FireAt(f32, f32),
+    Say(String),
+}
+
+// A character as a script sees it.
+pub struct Body {
+    pub name: String,
+    pub x: f32,
+    pub y: f32,
+    pub health: f32,
+}
+
+// A map trigger as a script sees it, in map pixels.
+pub struct TriggerView {
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+    pub player_inside: bool,
+}
+
+// Everything a script can read: itself as me, the player, every other character and the map's
+// triggers.
+pub struct ScriptInput {
+    pub me: Body,
+    pub player: Body,
+    pub others: Vec<Body>,
+    pub triggers: Vec<TriggerView>,
+}
+
+struct Program {
+    source: String,
+    // None once it failed to compile or run, until the file changes.
+    ast: Option<AST>,
+}
+
+// Behaviour scripts, from scripts/<name>.rhai. A script is run from the top once a frame for
+// every character it is attached to, with the input in scope and a state map that is kept
+// between frames. It can't touch the world directly, it only queues commands.
+pub struct Scripts {
+    engine: Engine,
+    programs: HashMap<String, Program>,
+    commands: Rc<RefCell<Vec<Command>>>,
+    log: Rc<RefCell<Vec<String>>>,
+}
+
+fn body(b: &Body) -> Dynamic {
+    let mut map = Map::new();
+    map.insert("name".into(), b.name.clone().into());
+    map.insert("x".into(), (b.x as f64).into());
+    map.insert("y".into(), (b.y as f64).into());
+    map.insert("health".into(), (b.health as f64).into());
+    map.into()
+}
+
+fn trigger(t: &TriggerView) -> Dynamic {
+    let mut map = Map::new();
+    map.insert("x".into(), (t.x as i64).into());
+    map.insert("y".into(), (t.y as i64).into());
+    map.insert("w".into(), (t.w as i64).into());
+    map.insert("h".into(), (t.h as i64).into());
+    map.insert("player_inside".into(), t.player_inside.into());
+    map.into()
+}
+
+fn path(name: &str) -> String {
+    format!("scripts/{}.rhai", name)
+}
+
+impl Scripts {
+    pub fn new() -> Scripts {
+        let commands = Rc::new(RefCell::new(Vec::new()));
+        let log = Rc::new(RefCell::new(Vec::new()));
+
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(MAX_CALL_LEVELS)
+            .set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH)
+            .set_max_array_size(MAX_COLLECTION)
+            .set_max_map_size(MAX_COLLECTION)
+            .set_max_string_size(MAX_STRING)
+            .set_module_resolver(DummyModuleResolver::new())
+            .disable_symbol("eval");
+
+        let l = log.clone();
+        engine.on_print(move |s| l.borrow_mut().push(s.to_string()));
+        let l = log.clone();
+        engine.on_debug(move |s, _, _| l.borrow_mut().push(s.to_string()));
+
+        let c = commands.clone();
+        engine.register_fn("move_towards", move |x: f64, y: f64| {
+            c.borrow_mut()
+                .push(Command::MoveTowards(x as f32, y as f32))
+        });
+        let c = commands.clone();
+        engine.register_fn("move_dir", move |x: f64, y: f64| {
+            c.borrow_mut().push(Command::MoveDir(x as f32, y as f32))
+        });
+        let c = commands.clone();
+        engine.register_fn("stop", move || c.borrow_mut().push(Command::Stop));
+        let c = commands.clone();
+        engine.register_fn("play_anim", move |anim: &str| {
+            c.borrow_mut().push(Command::PlayAnim(anim.to_string()))
+        });
+        let c = commands.clone();
+        engine.register_fn("play_sound", move |sound: &str| {
+            c.borrow_mut().push(Command::PlaySound(sound.to_string()))
+        });
+        let c = commands.clone();
+        engine.register_fn("fire_at", move |x: f64, y: f64| {
+            c.borrow_mut().push(Command::FireAt(x as f32, y as f32))
+        });
+        let c = commands.clone();
+        engine.register_fn("say", move |text: &str| {
+            c.borrow_mut().push(Command::Say(text.to_string()))
+        });
+        engine.register_fn("distance", |a: Map, b: Map| {
+            let get = |m: &Map, k: &str| m.get(k).and_then(|v| v.as_float().ok()).unwrap_or(0.0);
+            let (dx, dy) = (get(&a, "x") - get(&b, "x"), get(&a, "y") - get(&b, "y"));
+            (dx * dx + dy * dy).sqrt()
+        });
+
+        Scripts {
+            engine,
+            programs: HashMap::new(),
+            commands,
+            log,
+        }
+    }
+
+    fn compile(&self, name: &str, source: String) -> Program {
+        let ast = match self.engine.compile(&source) {
+            Ok(ast) => Some(ast),
+            Err(e) => {
+                self.log.borrow_mut().push(format!("{}: {}", path(name), e));
+                None
+            }
+        };
+        Program { source, ast }
+    }
+
+    // Runs a script once for one character. A script that fails is reported in the log and
+    // left alone until its file changes, and meanwhile its characters get no commands.
+    pub fn run(&mut self, name: &str, input: &ScriptInput, state: &mut Map) -> Vec<Command> {
+        if !self.programs.contains_key(name) {
+            let source = match assets::read_to_string(&path(name)) {
+                Ok(source) => source,
+                Err(e) => {
+                    self.log.borrow_mut().push(e);
+                    String::new()
+                }
+            };
+            let program = self.compile(name, source);
+            self.programs.insert(name.to_string(), program);
+        }
+        let program = self.programs.get_mut(name).unwrap();
+        let ast = match &program.ast {
+            Some(ast) => ast,
+            None => return Vec::new(),
+        };
+
+        let mut scope = Scope::new();
+        scope.push_constant("me", body(&input.me));
+        scope.push_constant("player", body(&input.player));
+        scope.push_constant(
+            "characters",
+            input.others.iter().map(body).collect::<Array>(),
+        );
+        scope.push_constant(
+            "triggers",
+            input.triggers.iter().map(trigger).collect::<Array>(),
+        );
+        scope.push("state", state.clone());
+
+        self.commands.borrow_mut().clear();
+        match self.engine.run_ast_with_scope(&mut scope, ast) {
+            Ok(()) => {
+                if let Some(new_state) = scope.get_value::<Map>("state") {
+                    *state = new_state;
+                }
+                self.commands.borrow_mut().drain(..).collect()
+            }
+            Err(e) => {
+                self.log
+                    .borrow_mut()
+                    .push(format!("{} ({}): {}", path(name), input.me.name, e));
+                program.ast = None;
+                Vec::new()
+            }
+        }
+    }
+
+    // Picks up changes to any script that has been run, including fixes to broken ones.
+    pub fn reload(&mut self) {
+        let changed: Vec<(String, String)> = self
+            .programs
+            .iter()
+            .filter_map(
+                |(name, program)| match assets::read_to_string(&path(name)) {
+                    Ok(source) if source != program.source => Some((name.clone(), source)),
+                    _ => None,
+                },
+            )
+            .collect();
+        for (name, source) in changed {
+            let program = self.compile(&name, source);
+            if program.ast.is_some() {
+                self.log
+                    .borrow_mut()
+                    .push(format!("reloaded {}", path(&name)));
+            }
+            self.programs.insert(name, program);
+        }
+    }
+
+    // For problems with what a script asked for, found by whoever carries it out.
+    pub fn log(&self, line: String) {
+        self.log.borrow_mut().push(line);
+    }
+
+    // Printed lines, errors and reloads since the last call.
+    pub fn take_log(&mut self) -> Vec<String> {
+        self.log.borrow_mut().drain(..).collect()
+    }
+}
+
+impl Default for Scripts {
+    fn default() -> Scripts {
+        Scripts::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input() -> ScriptInput {
+        let at = |name: &str, x, y| Body {
+            name: name.to_string(),
+            x,
+            y,
+            health: 100.0,
+        };
+        ScriptInput {
+            me: at("zombie", 0.0, 0.0),
+            player: at("player", 100.0, 0.0),
+            others: Vec::new(),
+            triggers: Vec::new(),
+        }
+    }
+
+    fn with_source(scripts: &mut Scripts, name: &str, source: &str) {
+        let program = scripts.compile(name, source.to_string());
+        scripts.programs.insert(name.to_string(), program);
+    }
+
+    #[test]
+    fn zombie_script_chases_when_close() {
+        let mut scripts = Scripts::new();
+        let mut state = Map::new();
+        let commands = scripts.run("zombie", &input(), &mut state);
+        assert_eq!(
+            commands,
+            vec![
+                Command::Say("Braaains!".to_string()),
+                Command::MoveTowards(100.0, 0.0)
+            ]
+        );
+        assert!(scripts.take_log().is_empty());
+    }
+
+    #[test]
+    fn state_is_kept_between_runs() {
+        let mut scripts = Scripts::new();
+        with_source(
+            &mut scripts,
+            "counter",
+            "if !(\"n\" in state) { state.n = 0; } state.n += 1; say(`${state.n}`);",
+        );
+        let mut state = Map::new();
+        scripts.run("counter", &input(), &mut state);
+        let commands = scripts.run("counter", &input(), &mut state);
+        assert_eq!(commands, vec![Command::Say("2".to_string())]);
+    }
+
+    #[test]
+    fn runaway_scripts_are_stopped() {
+        let mut scripts = Scripts::new();
+        with_source(&mut scripts, "spin", "loop { move_dir(1.0, 0.0); }");
+        let commands = scripts.run("spin", &input(), &mut Map::new());
+        assert!(commands.is_empty());
+        assert_eq!(scripts.take_log().len(), 1);
+        // Broken until it changes, without filling the log every frame.
+        scripts.run("spin", &input(), &mut Map::new());
+        assert!(scripts.take_log().is_empty());
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::{BTreeMap, HashMap};
+
+// Something that goes on a sprite sheet: a single frame, or an animation's frames side by side
+// in a strip the way parse_tiles reads them. Frames are given as (name, w, h) and a frame
+// named like "elf_m_run_anim_f2" is frame 2 of elf_m_run_anim.
+#[derive(Debug, PartialEq)]
+pub struct Sprite {
+    pub name: String,
+    // The size of one frame.
+    pub w: u32,
+    pub h: u32,
+    // Indexes into the frames given, in order. Animations can have just the one.
+    pub frames: Vec<usize>,
+    pub animated: bool,
+}
+
+impl Sprite {
+    // The size of the whole strip.
+    pub fn size(&self) -> (u32, u32) {
+        (self.w * self.frames.len() as u32, self.h)
+    }
+}
+
+// Splits "name_f12" into ("name", 12).
+fn frame_number(name: &str) -> Option<(&str, usize)> {
+    let at = name.rfind("_f")?;
+    let digits = &name[at + 2..];
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((&name[..at], digits.parse().ok()?))
+}
+
+// Groups frames into sprites, in name order. An animation's frames have to be numbered from 0
+// with none missing, and all be the same size.
+pub fn group(frames: &[(String, u32, u32)]) -> Result<Vec<Sprite>, String> {
+    let mut anims: BTreeMap<&str, BTreeMap<usize, usize>> = BTreeMap::new();
+    let mut sprites = Vec::new();
+    for (i, (name, w, h)) in frames.iter().enumerate() {
+        match frame_number(name) {
+            Some((anim, n)) => {
+                if anims.entry(anim).or_default().insert(n, i).is_some() {
+                    return Err(format!("{} has two frame {}s", anim, n));
+                }
+            }
+            None => sprites.push(Sprite {
+                name: name.clone(),
+                w: *w,
+                h: *h,
+                frames: vec![i],
+                animated: false,
+            }),
+        }
+    }
+
+    for (anim, numbered) in anims {
+        let (_, w, h) = frames[numbered.values().next().copied().unwrap()];
+        for (expected, (n, &i)) in numbered.iter().enumerate() {
+            if *n != expected {
+                return Err(format!("{} is missing frame {}", anim, expected));
+            }
+            if (frames[i].1, frames[i].2) != (w, h) {
+                return Err(format!(
+                    "{} is {}x{} but frame 0 of {} is {}x{}",
+                    frames[i].0, frames[i].1, frames[i].2, anim, w, h
+                ));
+            }
+        }
+        sprites.push(Sprite {
+            name: anim.to_string(),
+            w,
+            h,
+            frames: numbered.values().copied().collect(),
+            animated: true,
+        });
+    }
+
+    sprites.sort_by(|a, b| a.name.cmp(&b.name));
+    for pair in sprites.windows(2) {
+        if pair[0].name == pair[1].name {
+            return Err(format!("{} is both a frame and an animation", pair[0].name));
+        }
+    }
+    Ok(sprites)
+}
+
+// A width that makes a roughly square sheet: the smallest power of two that fits the widest
+// sprite and the square root of the total area.
+pub fn sheet_width(sizes: &[(u32, u32)], padding: u32) -> u32 {
+    let area: u32 = sizes
+        .iter()
+        .map(|(w, h)| (w + padding) * (h + padding))
+        .sum();
+    let widest = sizes.iter().map(|(w, _)| w + padding).max().unwrap_or(1);
+    ((area as f64).sqrt().ceil() as u32)
+        .max(widest)
+        .next_power_of_two()
+}
+
+// Packs rectangles onto shelves: tallest first, left to right along a shelf until the next one
+// doesn't fit, then on a new shelf below. Leaves padding pixels between them so filtering never
+// picks up a neighbour. Returns the top left of each, in the order given, and the height used.
+pub fn shelf_pack(
+    sizes: &[(u32, u32)],
+    width: u32,
+    padding: u32,
+) -> Result<(Vec<(u32, u32)>, u32), String> {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by(|&a, &b| {
+        sizes[b]
+            .1
+            .cmp(&sizes[a].1)
+            .then(sizes[b].0.cmp(&sizes[a].0))
+    });
+
+    let mut places = vec![(0, 0); sizes.len()];
+    let (mut x, mut y, mut shelf) = (0, 0, 0);
+    for i in order {
+        let (w, h) = sizes[i];
+        if w > width {
+            return Err(format!(
+                "a {}x{} sprite doesn't fit in {} wide",
+                w, h, width
+            ));
+        }
+        if x + w > width {
+            x = 0;
+            y += shelf + padding;
+            shelf = 0;
+        }
+        places[i] = (x, y);
+        x += w + padding;
+        shelf = shelf.max(h);
+    }
+    Ok((places, y + shelf))
+}
+
+// Property words, like "solid light=40", for each tile in an atlas in the format parse_tiles
+// reads, so they can be carried over when the sheet is rebuilt.
+pub fn atlas_props(text: &str) -> HashMap<String, String> {
+    let mut props = HashMap::new();
+    for line in text.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let numbers = words
+            .iter()
+            .skip(1)
+            .take_while(|w| w.parse::<i32>().is_ok())
+            .count();
+        if numbers >= 4 && words.len() > numbers + 1 {
+            props.insert(words[0].to_string(), words[numbers + 1..].join(" "));
+        }
+    }
+    props
+}
+
+// The atlas for a packed sheet, one line per sprite:
+//   name x y w h [props] for a tile
+//   name x y w h count [props] for an animation, with w and h the size of a frame
+pub fn atlas(sprites: &[Sprite], places: &[(u32, u32)], props: &HashMap<String, String>) -> String {
+    let mut text = String::new();
+    for (sprite, (x, y)) in sprites.iter().zip(places) {
+        text += &format!("{} {} {} {} {}", sprite.name, x, y, sprite.w, sprite.h);
+        if sprite.animated {
+            text += &format!(" {}", sprite.frames.len());
+        }
+        if let Some(props) = props.get(&sprite.name) {
+            text += &format!(" {}", props);
+        }
+        text += "\n";
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(names: &[(&str, u32, u32)]) -> Vec<(String, u32, u32)> {
+        names
+            .iter()
+            .map(|(name, w, h)| (name.to_string(), *w, *h))
+            .collect()
+    }
+
+    #[test]
+    fn groups_numbered_frames_into_animations() {
+        let sprites = group(&frames(&[
+            ("wall_hole_2", 16, 16),
+            ("imp_run_anim_f1", 16, 16),
+            ("imp_run_anim_f0", 16, 16),
+            ("wall_hole_1", 16, 16),
+        ]))
+        .unwrap();
+        let names: Vec<&str> = sprites.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["imp_run_anim", "wall_hole_1", "wall_hole_2"]);
+        assert_eq!(sprites[0].frames, vec![2, 1]);
+        assert!(sprites[0].animated);
+        assert_eq!(sprites[0].size(), (32, 16));
+
+        let gap = group(&frames(&[("a_f0", 16, 16), ("a_f2", 16, 16)]));
+        assert_eq!(gap.err().unwrap(), "a is missing frame 1");
+        let sizes = group(&frames(&[("a_f0", 16, 16), ("a_f1", 16, 28)]));
+        assert_eq!(
+            sizes.err().unwrap(),
+            "a_f1 is 16x28 but frame 0 of a is 16x16"
+        );
+    }
+
+    #[test]
+    fn packs_without_overlapping() {
+        let sizes = vec![(64, 16), (16, 28), (16, 16), (32, 16), (16, 16), (48, 32)];
+        let width = sheet_width(&sizes, 1);
+        assert_eq!(width, 128);
+        let (places, height) = shelf_pack(&sizes, width, 1).unwrap();
+
+        let rects: Vec<(u32, u32, u32, u32)> = places
+            .iter()
+            .zip(&sizes)
+            .map(|(&(x, y), &(w, h))| (x, y, w, h))
+            .collect();
+        for (i, a) in rects.iter().enumerate() {
+            assert!(a.0 + a.2 <= width && a.1 + a.3 <= height);
+            for b in &rects[i + 1..] {
+                let apart =
+                    a.0 + a.2 <= b.0 || b.0 + b.2 <= a.0 || a.1 + a.3 <= b.1 || b.1 + b.3 <= a.1;
+                assert!(apart, "{:?} and {:?} overlap", a, b);
+            }
+        }
+        assert!(shelf_pack(&[(65, 1)], 64, 0).is_err());
+    }
+
+    #[test]
+    fn writes_atlases_parse_tiles_reads() {
+        let sprites = group(&frames(&[
+            ("wall_mid", 16, 16),
+            ("imp_anim_f0", 16, 16),
+            ("imp_anim_f1", 16, 16),
+        ]))
+        .unwrap();
+        let props = atlas_props("wall_mid 32 16 16 16 solid light=40\nimp_anim 0 0 16 16 2\n");
+        let text = atlas(&sprites, &[(0, 0), (32, 0)], &props);
+        assert_eq!(
+            text,
+            "imp_anim 0 0 16 16 2\nwall_mid 32 0 16 16 solid light=40\n"
+        );
+    }
+}
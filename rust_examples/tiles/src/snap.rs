@@ -0,0 +1,45 @@
+// Turning world positions into screen pixels. Rounding the camera and everything drawn the
+// same way keeps them the same distance apart on screen, so whatever the camera follows
+// doesn't shimmer by a pixel as both move by fractions of one.
+//
+// Everything still lands on whole screen pixels. Drawing between them is left for later:
+// copy_ex_f needs SDL 2.0.10 and a newer sdl2 crate than this one builds against, and scaling
+// through a render target means sizing every rect draw_world and the lighting draw at the
+// target's scale.
+
+// The nearest multiple of grid. A grid of 0 or less leaves v as it is.
+pub fn snap(v: f32, grid: f32) -> f32 {
+    if grid <= 0.0 {
+        return v;
+    }
+    (v / grid).round() * grid
+}
+
+// Where a world coordinate lands on screen with the camera's edge at camera, both snapped to
+// grid world units first.
+pub fn to_screen(v: f32, camera: f32, grid: f32) -> i32 {
+    snap(v, grid).round() as i32 - snap(camera, grid).round() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn things_the_camera_follows_stay_put() {
+        // Truncating each separately put the player 599 or 600 pixels in as it moved.
+        for i in 0..100 {
+            let x = 1000.0 + i as f32 * 0.37;
+            assert_eq!(to_screen(x, x - 600.0, 1.0), 600);
+        }
+        assert_eq!(to_screen(-3.6, 0.0, 1.0), -4);
+    }
+
+    #[test]
+    fn snaps_to_coarser_grids() {
+        assert_eq!(snap(14.0, 10.0), 10.0);
+        assert_eq!(snap(15.0, 10.0), 20.0);
+        assert_eq!(to_screen(1234.0, 1003.0, 10.0), 230);
+        assert_eq!(snap(1.25, 0.0), 1.25);
+    }
+}
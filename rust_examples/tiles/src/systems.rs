@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use cgmath::{InnerSpace, Vector2};
+
+use tiles::ecs::Entity;
+
+use crate::behaviour::Orders;
+use crate::components::{Brain, Characters};
+use crate::projectile;
+use crate::{map_point, normalize, trigger, Dir, GameEvent, PlayerInput, World, FIRING_RANGE, FPS};
+
+// The systems World::step runs each frame, in the order it runs them. Each one goes through
+// the entities that have the components it needs, in id order.
+impl<'a> World<'a> {
+    // Where everyone with a brain wants to go this frame, scaled by their speed and by the
+    // ground under their feet.
+    pub fn think(
+        &mut self,
+        pi: &PlayerInput,
+        orders: &HashMap<Entity, Orders>,
+    ) -> HashMap<Entity, Vector2<f32>> {
+        let player_pos = self.characters.pos(self.player);
+        let mut moves = HashMap::new();
+        for (e, ai) in self.characters.ais.iter() {
+            let pos = self.characters.pos(e);
+            let (x, y) = map_point(pos);
+            let ground = self.terrain.at(x, y);
+
+            let dir = match &ai.brain {
+                Brain::Player => pi.to_dir(),
+                Brain::Talk(_) => Vector2::new(0.0, 0.0),
+                Brain::Chase | Brain::Script { .. } => {
+                    let to_player = player_pos - pos;
+                    if to_player.dot(to_player) < 5.0 {
+                        self.events.publish(GameEvent::EnemyReachedPlayer(e));
+                        Vector2::new(0.0, 0.0)
+                    } else if let Some(order) = orders.get(&e) {
+                        order.dir * 0.5
+                    } else {
+                        normalize(to_player) * 0.5
+                    }
+                }
+            };
+            moves.insert(e, dir * ai.speed * ground.speed);
+        }
+        moves
+    }
+
+    // Turns everyone the way they are going and moves every animation along.
+    pub fn animate(&mut self, moves: &HashMap<Entity, Vector2<f32>>) {
+        let anims = self.anims;
+        let Characters {
+            transforms,
+            sprites,
+            animators,
+            ..
+        } = &mut self.characters;
+        for (e, animator) in animators.iter_mut() {
+            let mov = moves
+                .get(&e)
+                .cloned()
+                .unwrap_or_else(|| Vector2::new(0.0, 0.0));
+            let transform = transforms.get_mut(e).unwrap();
+            if mov.x > 0.0 {
+                transform.dir = Dir::Right;
+            }
+            if mov.x < 0.0 {
+                transform.dir = Dir::Left;
+            }
+
+            let walking_before = animator.walking;
+            animator.walking = mov.x != 0.0 || mov.y != 0.0;
+            if animator.walking != walking_before {
+                animator.frame = 0;
+            }
+
+            if animator.frame_counter >= animator.frame_rate {
+                let len = anims[animator.anim(sprites.get(e).unwrap())].tiles.len();
+                animator.frame = (animator.frame + 1) % len;
+                animator.frame_counter = 0;
+            } else {
+                animator.frame_counter += 1;
+            }
+        }
+    }
+
+    // Counts down reloads and fires for everyone who is ready and has something to aim at. The
+    // player shoots the way they are going, or facing if standing still. Monsters shoot at the
+    // player once they are close enough, or wherever their script says.
+    pub fn shoot(
+        &mut self,
+        pi: &PlayerInput,
+        moves: &HashMap<Entity, Vector2<f32>>,
+        orders: &HashMap<Entity, Orders>,
+    ) -> Result<(), String> {
+        let player = self.player;
+        let player_pos = self.characters.pos(player);
+        let Characters {
+            transforms,
+            shooters,
+            ..
+        } = &mut self.characters;
+        for (e, shooter) in shooters.iter_mut() {
+            shooter.reload = shooter.reload.saturating_sub(1);
+            let transform = transforms.get(e).unwrap();
+            let mov = moves
+                .get(&e)
+                .cloned()
+                .unwrap_or_else(|| Vector2::new(0.0, 0.0));
+
+            let aim = if e == player {
+                if !pi.fire {
+                    None
+                } else if mov.x != 0.0 || mov.y != 0.0 {
+                    Some(mov)
+                } else if transform.dir.flip() {
+                    Some(Vector2::new(-1.0, 0.0))
+                } else {
+                    Some(Vector2::new(1.0, 0.0))
+                }
+            } else if let Some(order) = orders.get(&e) {
+                order.aim
+            } else {
+                let dir = player_pos - transform.pos;
+                if dir.dot(dir) < FIRING_RANGE * FIRING_RANGE && dir.dot(dir) >= 5.0 {
+                    Some(dir)
+                } else {
+                    None
+                }
+            };
+
+            if let (Some(aim), 0) = (aim, shooter.reload) {
+                self.projectiles
+                    .fire(self.anims, shooter.kind, transform.pos, aim, e == player)?;
+                shooter.reload = projectile::stats(shooter.kind).reload;
+            }
+        }
+        Ok(())
+    }
+
+    // Hurts everyone standing on damaging ground, and plays the player's footsteps on every
+    // other frame of the walk.
+    pub fn hurt_and_footsteps(&mut self) {
+        let Characters {
+            transforms,
+            healths,
+            ..
+        } = &mut self.characters;
+        for (e, health) in healths.iter_mut() {
+            let (x, y) = map_point(transforms.get(e).unwrap().pos);
+            let damage = self.terrain.at(x, y).damage;
+            if damage > 0.0 {
+                health.0 = (health.0 - damage / FPS as f32).max(0.0);
+            }
+        }
+
+        let (x, y) = map_point(self.characters.pos(self.player));
+        let animator = self.characters.animators.get(self.player).unwrap();
+        // is_multiple_of would need Rust 1.87.
+        #[allow(clippy::manual_is_multiple_of)]
+        let step = animator.walking && animator.frame_counter == 0 && animator.frame % 2 == 0;
+        if let Some(sound) = &self.terrain.at(x, y).footstep {
+            if step {
+                self.events.publish(GameEvent::Sound(sound.clone()));
+            }
+        }
+    }
+
+    // Moves everyone who wants to, unless it would take them into a wall, a forbidden area or
+    // a closed door.
+    pub fn movement(&mut self, moves: &HashMap<Entity, Vector2<f32>>) {
+        for (e, transform) in self.characters.transforms.iter_mut() {
+            let mov = match moves.get(&e) {
+                Some(mov) => *mov,
+                None => continue,
+            };
+            let new_pos = transform.pos + mov;
+
+            let (x, y) = map_point(new_pos);
+            let mut allowed = !self.terrain.at(x, y).solid;
+
+            for forbidden in &self.map.forbidden {
+                if trigger::inside(new_pos, forbidden.x, forbidden.y, forbidden.w, forbidden.h) {
+                    allowed = false;
+                }
+            }
+            for door in &self.map.doors {
+                if !door.open && trigger::inside(new_pos, door.x, door.y, door.w, door.h) {
+                    allowed = false;
+                }
+            }
+
+            if allowed {
+                transform.pos = new_pos;
+            }
+        }
+    }
+
+    // Monsters are gone once they run out of health.
+    pub fn deaths(&mut self) {
+        let player = self.player;
+        let dead: Vec<Entity> = self
+            .characters
+            .healths
+            .iter()
+            .filter(|(e, health)| *e != player && health.0 <= 0.0)
+            .map(|(e, _)| e)
+            .collect();
+        for e in dead {
+            self.events.publish(GameEvent::CharacterDied {
+                name: self.characters.names.get(e).cloned(),
+                pos: self.characters.pos(e),
+            });
+            self.characters.despawn(e);
+        }
+    }
+}
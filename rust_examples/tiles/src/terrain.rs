@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use crate::atlas::{Tile, TileProps, PLAIN};
+use crate::grid::CELL;
+use crate::map::Map;
+
+// The ground in every cell of a map, taken from the last tile drawn over the cell. Built once
+// per map so looking up what is under someone's feet doesn't mean searching the tile list.
+pub struct Terrain {
+    w: i32,
+    h: i32,
+    cells: Vec<TileProps>,
+}
+
+impl Terrain {
+    pub fn new(map: &Map, tiles: &HashMap<String, Tile>) -> Terrain {
+        let w = (map.width as i32 + CELL - 1) / CELL;
+        let h = (map.height as i32 + CELL - 1) / CELL;
+        let mut cells = vec![PLAIN; (w * h) as usize];
+
+        for map_tile in &map.tiles {
+            let tile = match tiles.get(&map_tile.tile) {
+                Some(tile) => tile,
+                None => continue,
+            };
+            let x0 = map_tile.x.div_euclid(CELL).max(0);
+            let y0 = map_tile.y.div_euclid(CELL).max(0);
+            let x1 = (map_tile.x + tile.w as i32 - 1).div_euclid(CELL).min(w - 1);
+            let y1 = (map_tile.y + tile.h as i32 - 1).div_euclid(CELL).min(h - 1);
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    cells[(y * w + x) as usize] = tile.props.clone();
+                }
+            }
+        }
+
+        Terrain { w, h, cells }
+    }
+
+    // The ground at a point in map pixels. Off the map is plain ground, the map's walls and
+    // forbidden areas are what keep people on it.
+    pub fn at(&self, x: i32, y: i32) -> &TileProps {
+        let (cx, cy) = (x.div_euclid(CELL), y.div_euclid(CELL));
+        if cx < 0 || cy < 0 || cx >= self.w || cy >= self.h {
+            return &PLAIN;
+        }
+        &self.cells[(cy * self.w + cx) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::atlas::parse_tiles;
+    use crate::map::MapTile;
+
+    fn map(tiles: &[(i32, i32, &str)]) -> Map {
+        Map {
+            tiles: tiles
+                .iter()
+                .map(|(x, y, tile)| MapTile {
+                    x: *x,
+                    y: *y,
+                    tile: tile.to_string(),
+                })
+                .collect(),
+            animations: Vec::new(),
+            forbidden: Vec::new(),
+            triggers: Vec::new(),
+            doors: Vec::new(),
+            entries: Vec::new(),
+            spawns: Vec::new(),
+            emitters: Vec::new(),
+            items: Vec::new(),
+            layout: Vec::new(),
+            darkness: 0,
+            width: 64,
+            height: 64,
+        }
+    }
+
+    #[test]
+    fn atlas_properties() {
+        let (tiles, _) = parse_tiles("tiles_list.txt", "generic".to_string()).unwrap();
+        assert!(tiles["wall_mid"].props.solid);
+        assert!(!tiles["floor_1"].props.solid);
+        assert_eq!(tiles["floor_1"].props.speed, 1.0);
+        assert!(tiles["hole"].props.damage > 0.0);
+        assert!(tiles["edge"].props.speed < 1.0);
+        assert!(tiles["floor_1"].props.footstep.is_some());
+        assert_eq!(tiles["wall_banner_red"].props.light, 40);
+        assert_eq!(
+            tiles["wall_banner_red"].props.light_colour,
+            [0xff, 0x60, 0x40]
+        );
+    }
+
+    #[test]
+    fn last_tile_drawn_wins() {
+        let (tiles, _) = parse_tiles("tiles_list.txt", "generic".to_string()).unwrap();
+        let terrain = Terrain::new(
+            &map(&[(0, 0, "floor_1"), (16, 0, "floor_1"), (16, 0, "wall_mid")]),
+            &tiles,
+        );
+        assert!(!terrain.at(8, 8).solid);
+        assert!(terrain.at(16, 0).solid);
+        assert!(terrain.at(31, 15).solid);
+        assert!(!terrain.at(32, 0).solid);
+    }
+
+    #[test]
+    fn off_the_map_is_plain() {
+        let (tiles, _) = parse_tiles("tiles_list.txt", "generic".to_string()).unwrap();
+        let terrain = Terrain::new(&map(&[(0, 0, "hole")]), &tiles);
+        assert!(terrain.at(4, 4).damage > 0.0);
+        assert_eq!(terrain.at(-1, 4), &PLAIN);
+        assert_eq!(terrain.at(4, 1000), &PLAIN);
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget, Texture, TextureCreator};
+
+use tiles::assets;
+
+use crate::render;
+
+// A glyph's corners in crossfont.png.
+pub struct Letter {
+    x: u32,
+    y: u32,
+    x2: u32,
+    y2: u32,
+}
+
+// One glyph per line, "A 0 9 7 16" for its character and corners.
+fn parse_letter(words: &[&str]) -> Result<(char, Letter), String> {
+    if words.len() != 5 {
+        return Err(format!("expected 5 fields, found {}", words.len()));
+    }
+    let mut chars = words[0].chars();
+    let c = match (chars.next(), chars.next()) {
+        (Some(c), None) => c,
+        _ => return Err(format!("{} isn't one character", words[0])),
+    };
+    let number = |word: &str| {
+        word.parse::<u32>()
+            .map_err(|_| format!("{} isn't a position", word))
+    };
+    let letter = Letter {
+        x: number(words[1])?,
+        y: number(words[2])?,
+        x2: number(words[3])?,
+        y2: number(words[4])?,
+    };
+    if letter.x2 < letter.x || letter.y2 < letter.y {
+        return Err(format!("{}'s corners are the wrong way round", c));
+    }
+    Ok((c, letter))
+}
+
+// Problems are reported as "path:line: message", like the atlases.
+pub fn load_letters(path: &str) -> Result<HashMap<char, Letter>, String> {
+    let mut letters: HashMap<char, Letter> = HashMap::new();
+
+    for (n, line) in assets::read_to_string(path)?.split('\n').enumerate() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        let (c, letter) = parse_letter(&words).map_err(|e| format!("{}:{}: {}", path, n + 1, e))?;
+        letters.insert(c, letter);
+    }
+    Ok(letters)
+}
+
+// Draws text with its top left corner at x, y, each font pixel scaled up to scale pixels.
+// Returns the width drawn.
+pub fn draw_text<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    font: &Texture,
+    letters: &HashMap<char, Letter>,
+    text: &str,
+    x: i32,
+    y: i32,
+    scale: u32,
+) -> Result<i32, String> {
+    let mut cursor = 0;
+
+    // The font only has capitals.
+    for c in text.to_ascii_uppercase().chars() {
+        if c == ' ' {
+            cursor += 4;
+            continue;
+        }
+        if let Some(letter) = letters.get(&c) {
+            let w = letter.x2 - letter.x;
+            let h = letter.y2 - letter.y;
+            canvas.copy(
+                font,
+                Rect::new(letter.x as i32, letter.y as i32, w, h),
+                Rect::new(x + cursor * scale as i32, y, w * scale, h * scale),
+            )?;
+            cursor += w as i32 + 1;
+        }
+    }
+
+    Ok(cursor * scale as i32)
+}
+
+// The font texture together with where each glyph is in it.
+pub struct Font<'t> {
+    texture: Texture<'t>,
+    letters: HashMap<char, Letter>,
+}
+
+impl<'t> Font<'t> {
+    // The glyphs in crossfont.png are black, which vanishes on the dark boxes text is drawn on,
+    // so they are turned white on loading. Each glyph pixel only has alpha set, so copying the
+    // alpha into every channel does that whatever order the channels are in.
+    pub fn load<T>(
+        texture_creator: &'t TextureCreator<T>,
+        png: &str,
+        txt: &str,
+    ) -> Result<Font<'t>, String> {
+        let texture = render::with_surface(png, |surface| {
+            if surface.pixel_format_enum().byte_size_per_pixel() != 4 {
+                return Err(format!("{}: expected 32 bit pixels", png));
+            }
+            surface.with_lock_mut(|pixels| {
+                for pixel in pixels.chunks_mut(4) {
+                    let alpha = *pixel.iter().max().unwrap();
+                    for c in pixel.iter_mut() {
+                        *c = alpha;
+                    }
+                }
+            });
+            texture_creator
+                .create_texture_from_surface(&*surface)
+                .map_err(|e| e.to_string())
+        })?;
+
+        Ok(Font {
+            texture,
+            letters: load_letters(txt)?,
+        })
+    }
+
+    pub fn draw<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        text: &str,
+        x: i32,
+        y: i32,
+        scale: u32,
+    ) -> Result<i32, String> {
+        draw_text(canvas, &self.texture, &self.letters, text, x, y, scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_font_and_refuses_backwards_glyphs() {
+        let letters = load_letters("crossfont.txt").unwrap();
+        assert_eq!(letters[&'A'].x2, 7);
+        assert_eq!(
+            parse_letter(&["B", "14", "9", "8", "16"]).err().unwrap(),
+            "B's corners are the wrong way round"
+        );
+        assert_eq!(
+            parse_letter(&["C", "15", "9", "20"]).err().unwrap(),
+            "expected 5 fields, found 4"
+        );
+    }
+}
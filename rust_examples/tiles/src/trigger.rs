@@ -0,0 +1,135 @@
+use cgmath::Vector2;
+
+use tiles::map::{TriggerAction, TriggerWhen};
+
+use crate::level;
+use crate::{GameEvent, PlayerInput, World};
+
+// Whether a world position (map pixels * 10) is inside a rectangle given in map pixels.
+pub fn inside(pos: Vector2<f32>, x: i32, y: i32, w: u32, h: u32) -> bool {
+    pos.x > (x * 10) as f32
+        && pos.x < ((x + w as i32) * 10) as f32
+        && pos.y > (y * 10) as f32
+        && pos.y < ((y + h as i32) * 10) as f32
+}
+
+impl<'a> World<'a> {
+    // Fires the triggers the player entered, left or interacted with this frame. Actions that
+    // change the world happen here; sounds and messages are published as events. A trigger
+    // naming a door the map doesn't have is reported and skipped, tiles-lint catches those.
+    pub fn update_triggers(&mut self, pi: &PlayerInput) {
+        let player_pos = self.characters.pos(self.player);
+
+        let mut actions: Vec<TriggerAction> = Vec::new();
+        for (i, trigger) in self.map.triggers.iter().enumerate() {
+            let now = inside(player_pos, trigger.x, trigger.y, trigger.w, trigger.h);
+            let was = self.inside_triggers[i];
+            self.inside_triggers[i] = now;
+            if now && !was {
+                self.events.publish(GameEvent::TriggerEntered(i));
+            }
+            if was && !now {
+                self.events.publish(GameEvent::TriggerLeft(i));
+            }
+
+            let fire = match trigger.when {
+                TriggerWhen::Enter => now && !was,
+                TriggerWhen::Exit => was && !now,
+                TriggerWhen::Interact => now && pi.interact,
+            };
+            if fire {
+                actions.extend(trigger.actions.iter().cloned());
+            }
+        }
+
+        for action in actions {
+            match action {
+                TriggerAction::Door { door } => {
+                    let d = match self.map.doors.iter_mut().find(|d| d.name == door) {
+                        Some(d) => d,
+                        None => {
+                            eprintln!("trigger opens unknown door {}", door);
+                            continue;
+                        }
+                    };
+                    match &d.key {
+                        Some(key) if !self.inventory.take(key) => {
+                            self.events.publish(GameEvent::Message(format!(
+                                "It needs a {}",
+                                key.replace('_', " ")
+                            )));
+                        }
+                        _ => {
+                            d.key = None;
+                            d.open = !d.open;
+                        }
+                    }
+                }
+                // Landing inside another trigger doesn't fire it, the same as arriving on a
+                // level, so two pads can't bounce the player between them.
+                TriggerAction::Teleport { x, y } => {
+                    let pos = Vector2::new((x * 10) as f32, (y * 10) as f32);
+                    self.characters.transforms.get_mut(self.player).unwrap().pos = pos;
+                    self.inside_triggers = level::triggers_inside(&self.map, pos);
+                }
+                TriggerAction::Travel { map, entry } => self.travel(map, entry),
+                TriggerAction::Sound { sound } => self.events.publish(GameEvent::Sound(sound)),
+                TriggerAction::Message { text } => self.events.publish(GameEvent::Message(text)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector2;
+
+    use crate::{level, load_atlases, PlayerInput, World};
+    use tiles::config::{Assets, Gameplay};
+    use tiles::map::{MapTrigger, TriggerAction, TriggerWhen};
+
+    fn pad(x: i32, to: (i32, i32)) -> MapTrigger {
+        MapTrigger {
+            x,
+            y: 0,
+            w: 16,
+            h: 16,
+            when: TriggerWhen::Enter,
+            actions: vec![TriggerAction::Teleport { x: to.0, y: to.1 }],
+        }
+    }
+
+    #[test]
+    fn teleporting_onto_a_pad_doesnt_fire_it() {
+        let (tiles, anims) = load_atlases(&Assets::default().atlases).unwrap();
+        let tuning = Gameplay::default();
+        let mut world = World::new("map", &tiles, &anims, 0, &tuning).unwrap();
+        world.map.triggers = vec![pad(0, (108, 8)), pad(100, (8, 8))];
+        world.inside_triggers = level::triggers_inside(&world.map, Vector2::new(0.0, 0.0));
+        let still = PlayerInput {
+            up: false,
+            down: false,
+            left: false,
+            right: false,
+            interact: false,
+            fire: false,
+        };
+
+        world
+            .characters
+            .transforms
+            .get_mut(world.player)
+            .unwrap()
+            .pos = Vector2::new(80.0, 80.0);
+        world.update_triggers(&still);
+        assert_eq!(
+            world.characters.pos(world.player),
+            Vector2::new(1080.0, 80.0)
+        );
+        world.update_triggers(&still);
+        assert_eq!(
+            world.characters.pos(world.player),
+            Vector2::new(1080.0, 80.0)
+        );
+    }
+}
@@ -0,0 +1,107 @@
+// Fits a fixed size virtual screen into a window of any size. The virtual screen is scaled up
+// by a whole number when the window has room for it, or down to a half, a third and so on when
+// it doesn't, so every virtual pixel comes out the same size. What is left over becomes black
+// bars around it.
+pub struct Viewport {
+    pub w: u32,
+    pub h: u32,
+    window_w: u32,
+    window_h: u32,
+}
+
+impl Viewport {
+    // Starts out with a window exactly the size of the virtual screen.
+    pub fn new(w: u32, h: u32) -> Viewport {
+        Viewport {
+            w,
+            h,
+            window_w: w,
+            window_h: h,
+        }
+    }
+
+    // A minimised window can report a size of zero, which is treated as one pixel.
+    pub fn resize(&mut self, window_w: u32, window_h: u32) {
+        self.window_w = window_w.max(1);
+        self.window_h = window_h.max(1);
+    }
+
+    // Window pixels per virtual pixel.
+    pub fn scale(&self) -> f32 {
+        let up = (self.window_w / self.w).min(self.window_h / self.h);
+        if up >= 1 {
+            return up as f32;
+        }
+        let down = self
+            .w
+            .div_ceil(self.window_w)
+            .max(self.h.div_ceil(self.window_h));
+        1.0 / down as f32
+    }
+
+    // Where the virtual screen goes in the window, as x, y, w, h in window pixels.
+    pub fn rect(&self) -> (i32, i32, u32, u32) {
+        let scale = self.scale();
+        let w = (self.w as f32 * scale) as u32;
+        let h = (self.h as f32 * scale) as u32;
+        (
+            (self.window_w - w) as i32 / 2,
+            (self.window_h - h) as i32 / 2,
+            w,
+            h,
+        )
+    }
+
+    // The virtual pixel under a point in the window, or None if it is on a bar.
+    pub fn to_virtual(&self, x: i32, y: i32) -> Option<(i32, i32)> {
+        let (left, top, w, h) = self.rect();
+        if x < left || y < top || x >= left + w as i32 || y >= top + h as i32 {
+            return None;
+        }
+        let scale = self.scale();
+        Some((
+            ((x - left) as f32 / scale) as i32,
+            ((y - top) as f32 / scale) as i32,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_up_by_whole_numbers_with_bars() {
+        let mut viewport = Viewport::new(160, 144);
+        viewport.resize(1920, 1080);
+        assert_eq!(viewport.scale(), 7.0);
+        assert_eq!(viewport.rect(), (400, 36, 1120, 1008));
+
+        viewport.resize(160, 144);
+        assert_eq!(viewport.rect(), (0, 0, 160, 144));
+    }
+
+    #[test]
+    fn scales_down_when_the_window_is_too_small() {
+        let mut viewport = Viewport::new(1200, 1200);
+        viewport.resize(1920, 1080);
+        assert_eq!(viewport.scale(), 0.5);
+        assert_eq!(viewport.rect(), (660, 240, 600, 600));
+
+        viewport.resize(0, 0);
+        assert_eq!(viewport.rect().2, 1);
+    }
+
+    #[test]
+    fn maps_the_mouse_back_to_virtual_pixels() {
+        let mut viewport = Viewport::new(160, 144);
+        viewport.resize(1920, 1080);
+        assert_eq!(viewport.to_virtual(400, 36), Some((0, 0)));
+        assert_eq!(
+            viewport.to_virtual(400 + 7 * 10 + 6, 36 + 7 * 5),
+            Some((10, 5))
+        );
+        assert_eq!(viewport.to_virtual(399, 500), None);
+        assert_eq!(viewport.to_virtual(1520, 500), None);
+    }
+}